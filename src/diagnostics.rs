@@ -0,0 +1,140 @@
+use std::collections::HashSet;
+
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, Position, Range};
+
+// Drop diagnostics reported more than once at the same range with the same
+// message, severity and source, e.g. by two plugins running the same rules.
+// The first occurrence is kept.
+pub fn dedup(diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+    let mut seen = HashSet::new();
+
+    diagnostics
+        .into_iter()
+        .filter(|diagnostic| {
+            seen.insert((
+                diagnostic.range.start.line,
+                diagnostic.range.start.character,
+                diagnostic.range.end.line,
+                diagnostic.range.end.character,
+                diagnostic.message.clone(),
+                severity_level(diagnostic.severity),
+                diagnostic.source.clone(),
+            ))
+        })
+        .collect()
+}
+
+// Order diagnostics by position, the most severe first on the same position.
+// The sort is stable, so the linter's order is kept otherwise.
+pub fn sort(mut diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+    diagnostics.sort_by_key(|diagnostic| {
+        (
+            diagnostic.range.start.line,
+            diagnostic.range.start.character,
+            severity_level(diagnostic.severity),
+        )
+    });
+
+    diagnostics
+}
+
+// Keep at most `max` diagnostics, with a single summary diagnostic for the
+// rest. A `max` of 0 keeps everything.
+pub fn truncate(mut diagnostics: Vec<Diagnostic>, max: usize) -> Vec<Diagnostic> {
+    if max == 0 || diagnostics.len() <= max {
+        return diagnostics;
+    }
+
+    let suppressed = diagnostics.len() - max;
+    diagnostics.truncate(max);
+    diagnostics.push(Diagnostic {
+        range: Range::new(Position::new(0, 0), Position::new(0, 0)),
+        severity: Some(DiagnosticSeverity::INFORMATION),
+        source: Some("checkmate".to_string()),
+        message: format!("{} more issues suppressed", suppressed),
+        ..Diagnostic::default()
+    });
+
+    diagnostics
+}
+
+// Numeric level of a severity, lower is more severe and 0 is unset.
+fn severity_level(severity: Option<DiagnosticSeverity>) -> u8 {
+    match severity {
+        Some(DiagnosticSeverity::ERROR) => 1,
+        Some(DiagnosticSeverity::WARNING) => 2,
+        Some(DiagnosticSeverity::INFORMATION) => 3,
+        Some(DiagnosticSeverity::HINT) => 4,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::{phpcs::PhpcsPlugin, Plugin, PluginSetting};
+    use crate::test_support::fixture;
+    use tower_lsp::lsp_types::Url;
+
+    #[test]
+    fn drops_duplicate_phpcs_messages() {
+        let diagnostics = PhpcsPlugin
+            .parse(
+                &PluginSetting::default(),
+                &Url::from_file_path("/project/src/Foo.php").unwrap(),
+                &fixture("phpcs/duplicates.json"),
+                b"",
+            )
+            .unwrap();
+        assert_eq!(diagnostics.len(), 2);
+
+        let diagnostics = dedup(diagnostics);
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].message,
+            "Missing doc comment for function bar()"
+        );
+    }
+
+    #[test]
+    fn keeps_messages_from_other_sources() {
+        let diagnostic = Diagnostic::new_simple(Range::default(), "Unused variable".to_string());
+        let other_source = Diagnostic {
+            source: Some("phpstan".to_string()),
+            ..diagnostic.clone()
+        };
+
+        assert_eq!(dedup(vec![diagnostic, other_source]).len(), 2);
+    }
+
+    #[test]
+    fn sorts_by_position_then_severity() {
+        let at = |line, character, severity, message: &str| Diagnostic {
+            range: Range::new(
+                Position::new(line, character),
+                Position::new(line, character),
+            ),
+            severity: Some(severity),
+            message: message.to_string(),
+            ..Diagnostic::default()
+        };
+        let diagnostics = vec![
+            at(7, 0, DiagnosticSeverity::ERROR, "last line"),
+            at(2, 4, DiagnosticSeverity::HINT, "hint"),
+            at(2, 4, DiagnosticSeverity::ERROR, "error"),
+            at(2, 0, DiagnosticSeverity::WARNING, "first column"),
+            at(2, 4, DiagnosticSeverity::HINT, "second hint"),
+        ];
+
+        let messages: Vec<String> = sort(diagnostics)
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect();
+
+        assert_eq!(
+            messages,
+            vec!["first column", "error", "hint", "second hint", "last line"]
+        );
+    }
+}
@@ -1,27 +1,155 @@
-use std::{collections::HashMap, vec};
+use std::{
+    collections::hash_map::DefaultHasher,
+    collections::{HashMap, HashSet},
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    str,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+    vec,
+};
 
-use dashmap::DashMap;
-use log::info;
-use serde_json::Value;
+use dashmap::{DashMap, DashSet};
+use futures::stream::{self, StreamExt};
+use glob::Pattern;
+use ignore::gitignore::Gitignore;
+use log::{error, info};
+use serde_derive::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::Semaphore;
+use tokio_util::sync::CancellationToken;
 use tower_lsp::jsonrpc::Result;
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer};
 
+use crate::diagnostics::{dedup, sort, truncate};
+use crate::persist;
 use crate::plugins::{
-    eslint::EslintPlugin, phpcs::PhpcsPlugin, phpstan::PhpstanPlugin, stylelint::StylelintPlugin,
-    Plugin, PluginSetting,
+    ameba::AmebaPlugin, biome::BiomePlugin, black::BlackPlugin, cargo_check::CargoCheckPlugin,
+    clang_tidy::ClangTidyPlugin, dart::DartPlugin, eslint::EslintPlugin, execute, find_executable,
+    find_project_binary, gofmt::GofmtPlugin, hadolint::HadolintPlugin,
+    markuplint::MarkuplintPlugin, parse_severity, php_cs_fixer::PhpCsFixerPlugin,
+    phpcs::PhpcsPlugin, phpmd::PhpmdPlugin, phpstan::PhpstanPlugin, prettier::PrettierPlugin,
+    revive::RevivePlugin, rubocop_format::RubocopFormatPlugin,
+    sqlfluff_format::SqlfluffFormatPlugin, stylelint::StylelintPlugin, tsc::TscPlugin,
+    vale::ValePlugin, AppendMode, Plugin, PluginSetting, DEFAULT_MAX_OUTPUT_BYTES,
 };
+use crate::throttle::PublishThrottle;
+
+// Lists the available plugins and which of them are installed.
+const PLUGINS_COMMAND: &str = "checkmate/plugins";
+// Reports detection problems of the configured plugins.
+const DOCTOR_COMMAND: &str = "checkmate.doctor";
+// Lints the document passed as the first argument.
+const LINT_COMMAND: &str = "checkmate.lint";
+
+// Events a plugin can run on, set per plugin with run_on.
+const RUN_ON_TRIGGERS: [&str; 3] = ["save", "open", "change"];
+// Lints requested by the user run every plugin.
+const MANUAL_TRIGGER: &str = "manual";
+// Lints the files of all workspace folders.
+const WORKSPACE_LINT_COMMAND: &str = "checkmate.lintWorkspace";
+// A workspace lint runs the plugins for each file, apart from the ones which
+// check the whole project, they run once per folder.
+const WORKSPACE_TRIGGER: &str = "workspace";
+const PROJECT_TRIGGER: &str = "project";
+// Directories a workspace lint doesn't look into, next to hidden ones.
+const WORKSPACE_SKIPPED_DIRS: [&str; 3] = ["node_modules", "vendor", "target"];
+// Saves of a file within this window are linted once, e.g. when format on
+// save writes the file again right after the user saved it.
+const SAVE_SETTLE: Duration = Duration::from_millis(200);
+// Files matching its gitignore style patterns are never linted.
+const IGNORE_FILE: &str = ".checkmateignore";
 
 pub struct Lsp {
     pub client: Client,
     pub client_settings: ClientSettings,
     pub server_settings: ServerSettings,
+    pub documents: DashMap<Url, String>,
+    // Last published diagnostics per file, for each plugin.
+    pub diagnostics: DashMap<Url, HashMap<String, Vec<Diagnostic>>>,
+    // Hash of the file contents at the last lint.
+    pub content_hashes: DashMap<Url, u64>,
+    // Hash of the file contents at the last lint with the current
+    // configuration, saving the same contents reuses its diagnostics.
+    pub linted_hashes: DashMap<Url, u64>,
+    // Cancels the running lint of a file and trigger, on a newer lint or
+    // when closed.
+    pub lint_tokens: DashMap<(Url, String), CancellationToken>,
+    // Files a project scoped plugin reported on when linting a file, per
+    // linted file and plugin.
+    pub reported_uris: DashMap<(Url, String), HashSet<Url>>,
+    // Saves waiting to settle, to collapse rapid saves.
+    pub last_saves: DashMap<Url, Instant>,
+    // Spaces out diagnostic publishes for slow clients.
+    pub publish_throttle: PublishThrottle,
+}
+
+impl Lsp {
+    pub fn new(client: Client) -> Self {
+        Lsp {
+            client,
+            client_settings: ClientSettings::new(),
+            server_settings: ServerSettings::new(),
+            documents: DashMap::new(),
+            diagnostics: DashMap::new(),
+            content_hashes: DashMap::new(),
+            linted_hashes: DashMap::new(),
+            lint_tokens: DashMap::new(),
+            reported_uris: DashMap::new(),
+            last_saves: DashMap::new(),
+            publish_throttle: PublishThrottle::default(),
+        }
+    }
+}
+
+// Layout of .checkmate.toml, the plugin settings take the same values as the
+// editor settings.
+#[derive(Debug, Default, Deserialize)]
+struct ProjectConfig {
+    #[serde(default)]
+    plugins: HashMap<String, ProjectPluginSetting>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ProjectPluginSetting {
+    cmd: String,
+    args: String,
+    filetypes: String,
+    exclude: String,
+    use_baseline: Option<bool>,
+    config_path: String,
+    retry_on_empty: bool,
+    compilation_database: String,
+    severity_map: HashMap<String, String>,
+    ignore_rules: String,
+    env: HashMap<String, String>,
+    run_on: String,
+    standard: Option<String>,
+    analyse_paths: String,
+    message_prefix: Option<Value>,
+    max_output_bytes: Option<usize>,
+    strip_project_root: bool,
+    append_file: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DidFocusParams {
+    pub text_document: TextDocumentIdentifier,
 }
 
 #[derive(Debug, Clone)]
 pub struct ClientSettings {
     pub plugins: Vec<String>,
     pub settings: DashMap<String, String>,
+    pub relint_triggers: DashMap<String, String>,
+    // Paths of the workspace folders, to their names.
+    pub workspace_folders: DashMap<String, String>,
 }
 
 impl ClientSettings {
@@ -29,6 +157,8 @@ impl ClientSettings {
         ClientSettings {
             plugins: vec![],
             settings: DashMap::new(),
+            relint_triggers: DashMap::new(),
+            workspace_folders: DashMap::new(),
         }
     }
 }
@@ -36,6 +166,16 @@ impl ClientSettings {
 pub struct ServerSettings {
     pub available_plugins: HashMap<String, Box<dyn Plugin + Send + Sync>>,
     pub installed_plugins: DashMap<String, PluginSetting>,
+    // Settings of all configured plugins, as the user set them.
+    pub configured_plugins: DashMap<String, PluginSetting>,
+    // Plugins detected for other workspace folders, by folder and plugin id.
+    pub folder_plugins: DashMap<(String, String), Option<PluginSetting>>,
+    // Caps the number of plugin processes running at once.
+    pub process_limit: RwLock<Arc<Semaphore>>,
+    // Patterns from the .checkmateignore at the root.
+    pub ignore: RwLock<Gitignore>,
+    // Unknown plugin ids the user was warned about, once per session.
+    pub warned_unknown_plugins: DashSet<String>,
 }
 
 impl ServerSettings {
@@ -45,12 +185,52 @@ impl ServerSettings {
         // All supported plugins.
         available_plugins.insert(String::from("phpcs"), Box::<PhpcsPlugin>::default());
         available_plugins.insert(String::from("phpstan"), Box::<PhpstanPlugin>::default());
+        available_plugins.insert(String::from("phpmd"), Box::<PhpmdPlugin>::default());
         available_plugins.insert(String::from("eslint"), Box::<EslintPlugin>::default());
         available_plugins.insert(String::from("stylelint"), Box::<StylelintPlugin>::default());
+        available_plugins.insert(String::from("ameba"), Box::<AmebaPlugin>::default());
+        available_plugins.insert(String::from("biome"), Box::<BiomePlugin>::default());
+        available_plugins.insert(String::from("black"), Box::<BlackPlugin>::default());
+        available_plugins.insert(
+            String::from("cargo-check"),
+            Box::<CargoCheckPlugin>::default(),
+        );
+        available_plugins.insert(
+            String::from("clang-tidy"),
+            Box::<ClangTidyPlugin>::default(),
+        );
+        available_plugins.insert(String::from("dart"), Box::<DartPlugin>::default());
+        available_plugins.insert(String::from("gofmt"), Box::<GofmtPlugin>::default());
+        available_plugins.insert(String::from("hadolint"), Box::<HadolintPlugin>::default());
+        available_plugins.insert(
+            String::from("markuplint"),
+            Box::<MarkuplintPlugin>::default(),
+        );
+        available_plugins.insert(
+            String::from("php-cs-fixer"),
+            Box::<PhpCsFixerPlugin>::default(),
+        );
+        available_plugins.insert(String::from("prettier"), Box::<PrettierPlugin>::default());
+        available_plugins.insert(String::from("vale"), Box::<ValePlugin>::default());
+        available_plugins.insert(String::from("tsc"), Box::<TscPlugin>::default());
+        available_plugins.insert(String::from("revive"), Box::<RevivePlugin>::default());
+        available_plugins.insert(
+            String::from("rubocop-format"),
+            Box::<RubocopFormatPlugin>::default(),
+        );
+        available_plugins.insert(
+            String::from("sqlfluff-format"),
+            Box::<SqlfluffFormatPlugin>::default(),
+        );
 
         ServerSettings {
             available_plugins,
             installed_plugins: DashMap::new(),
+            configured_plugins: DashMap::new(),
+            folder_plugins: DashMap::new(),
+            process_limit: RwLock::new(Arc::new(Semaphore::new(default_max_concurrency()))),
+            ignore: RwLock::new(Gitignore::empty()),
+            warned_unknown_plugins: DashSet::new(),
         }
     }
 }
@@ -58,253 +238,3122 @@ impl ServerSettings {
 #[tower_lsp::async_trait]
 impl LanguageServer for Lsp {
     async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        // Clients opening a single file may not send a root.
+        let root_uri = match params.root_uri.clone() {
+            Some(root_uri) => root_uri,
+            None => {
+                let root_uri = fallback_root_uri(&params);
+                info!(
+                    "No root uri sent, using {}",
+                    root_uri.as_ref().map(Url::as_str).unwrap_or("none")
+                );
+                root_uri.unwrap_or_else(|| Url::parse("file:///").unwrap())
+            }
+        };
         self.client_settings
             .settings
-            .insert("root_uri".to_string(), params.root_uri.unwrap().to_string());
+            .insert("root_uri".to_string(), root_uri.to_string());
+
+        let root = project_root(&self.client_settings.settings);
+        self.client_settings
+            .workspace_folders
+            .insert(root.clone(), root);
+        for folder in params.workspace_folders.unwrap_or_default() {
+            if let Ok(path) = folder.uri.to_file_path() {
+                self.client_settings
+                    .workspace_folders
+                    .insert(path.to_string_lossy().to_string(), folder.name);
+            }
+        }
+
+        let work_done_progress = params
+            .capabilities
+            .window
+            .and_then(|window| window.work_done_progress)
+            .unwrap_or(false);
+        if work_done_progress {
+            self.client_settings
+                .settings
+                .insert("work_done_progress".to_string(), "true".to_string());
+        }
+
+        let watched_files_registration = params
+            .capabilities
+            .workspace
+            .and_then(|workspace| workspace.did_change_watched_files)
+            .and_then(|watched_files| watched_files.dynamic_registration)
+            .unwrap_or(false);
+        if watched_files_registration {
+            self.client_settings
+                .settings
+                .insert("watched_files_registration".to_string(), "true".to_string());
+        }
+
         Ok(InitializeResult {
             server_info: None,
-            capabilities: ServerCapabilities {
-                text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::FULL,
-                )),
-                workspace: Some(WorkspaceServerCapabilities {
-                    workspace_folders: Some(WorkspaceFoldersServerCapabilities {
-                        supported: Some(true),
-                        change_notifications: Some(OneOf::Left(true)),
-                    }),
-                    file_operations: None,
-                }),
-                ..ServerCapabilities::default()
-            },
+            capabilities: server_capabilities(),
         })
     }
 
     async fn initialized(&self, _params: InitializedParams) {
-        // parse editor settings.
-        let editor_settings_items = ConfigurationItem {
-            scope_uri: None,
-            section: Some("checkmate.plugins".to_string()),
-        };
-
-        let editor_settings = self
-            .client
-            .configuration(vec![editor_settings_items])
+        // Saving many files at once shouldn't spawn a process for each.
+        let max_concurrency = self
+            .fetch_editor_setting("checkmate.max_concurrency")
             .await
-            .expect("Cant fetch code editor config.");
+            .and_then(|max_concurrency| max_concurrency.as_u64())
+            .filter(|max_concurrency| *max_concurrency > 0)
+            .map(|max_concurrency| max_concurrency as usize)
+            .unwrap_or_else(default_max_concurrency);
+        *self.server_settings.process_limit.write().unwrap() =
+            Arc::new(Semaphore::new(max_concurrency));
 
-        let editor_plugins = parse_client_editor_settings(editor_settings);
+        *self.server_settings.ignore.write().unwrap() =
+            load_ignore_file(&project_root(&self.client_settings.settings));
 
-        for (plugin_id, settings) in editor_plugins {
-            let plugin_discovered = self.server_settings.available_plugins.get(&plugin_id);
+        self.configure().await;
 
-            if plugin_discovered.is_none() {
-                self.client
-                    .log_message(
-                        MessageType::ERROR,
-                        format!("{} plugin does not exist.", plugin_id),
-                    )
-                    .await;
-                info!("{} plugin does not exist.", plugin_id);
+        // Files changed outside the editor, e.g. by a git checkout, are
+        // re-linted when they're open.
+        let watch_files = self
+            .fetch_editor_setting("checkmate.watch_files")
+            .await
+            .and_then(|watch_files| watch_files.as_bool())
+            .unwrap_or(true);
+        if watch_files
+            && self
+                .client_settings
+                .settings
+                .contains_key("watched_files_registration")
+        {
+            self.register_file_watcher().await;
+        }
 
-                continue;
+        self.client
+            .log_message(MessageType::INFO, "checkmate initialized!")
+            .await;
+    }
+
+    async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
+        for folder in params.event.removed {
+            if let Ok(path) = folder.uri.to_file_path() {
+                let path = path.to_string_lossy().to_string();
+                self.client_settings.workspace_folders.remove(&path);
+                self.server_settings
+                    .folder_plugins
+                    .retain(|(root, _), _| *root != path);
             }
+        }
 
-            let plugin = plugin_discovered.unwrap();
+        for folder in params.event.added {
+            if let Ok(path) = folder.uri.to_file_path() {
+                self.client_settings
+                    .workspace_folders
+                    .insert(path.to_string_lossy().to_string(), folder.name);
+            }
+        }
+    }
 
-            if let Some(default_plugin_setting) =
-                plugin.is_installed(self.client_settings.settings.clone())
-            {
-                self.client
-                    .log_message(
-                        MessageType::LOG,
-                        format!(
-                            "Plugin {} is installed, executable path is {}",
-                            plugin_id, default_plugin_setting.cmd
-                        ),
-                    )
-                    .await;
+    async fn did_change_configuration(&self, _params: DidChangeConfigurationParams) {
+        // The settings are pulled again, like on startup.
+        self.configure().await;
+    }
 
-                let mut plugin_settings = PluginSetting::default();
+    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let file_uri = params.text_document_position_params.text_document.uri;
+        let position = params.text_document_position_params.position;
 
-                // CMD
-                if !settings.cmd.is_empty() {
-                    plugin_settings.cmd.clone_from(&settings.cmd);
-                } else {
-                    plugin_settings.cmd.clone_from(&default_plugin_setting.cmd);
-                }
+        let file_diagnostics = match self.diagnostics.get(&file_uri) {
+            Some(file_diagnostics) => file_diagnostics,
+            None => return Ok(None),
+        };
 
-                // ARGS.
-                let mut plugin_args = default_plugin_setting.args.clone();
-                for arg in settings.args {
-                    plugin_args.push(arg);
-                }
-                plugin_settings.args = plugin_args;
+        let contents: Vec<String> = file_diagnostics
+            .values()
+            .flatten()
+            .filter(|diagnostic| range_contains(&diagnostic.range, &position))
+            .map(hover_markdown)
+            .collect();
+
+        if contents.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: contents.join("\n\n---\n\n"),
+            }),
+            range: None,
+        }))
+    }
 
-                // Filetypes.
-                if !settings.filetypes.is_empty() {
-                    let mut plugin_filetypes = default_plugin_setting.filetypes.clone();
-                    for i in settings.filetypes {
-                        plugin_filetypes.push(i);
+    async fn execute_command(&self, params: ExecuteCommandParams) -> Result<Option<Value>> {
+        match &params.command[..] {
+            PLUGINS_COMMAND => Ok(Some(self.plugins_status())),
+            LINT_COMMAND => {
+                let file_uri = params
+                    .arguments
+                    .first()
+                    .and_then(|uri| uri.as_str())
+                    .and_then(|uri| Url::parse(uri).ok());
+                let file_uri = match file_uri {
+                    Some(file_uri) => file_uri,
+                    None => {
+                        return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                            "{} expects a document URI",
+                            LINT_COMMAND
+                        )))
                     }
-                    plugin_settings.filetypes = plugin_filetypes;
-                } else {
-                    plugin_settings
-                        .filetypes
-                        .clone_from(&default_plugin_setting.filetypes);
+                };
+
+                self.client
+                    .log_message(MessageType::INFO, "Lint requested, running linters...")
+                    .await;
+
+                if let Some(content_hash) = file_content_hash(&file_uri) {
+                    self.content_hashes.insert(file_uri.clone(), content_hash);
+                    self.linted_hashes.insert(file_uri.clone(), content_hash);
                 }
+                self.lint(file_uri, MANUAL_TRIGGER).await;
 
-                self.server_settings
-                    .installed_plugins
-                    .insert(plugin_id, plugin_settings);
+                Ok(None)
+            }
+            WORKSPACE_LINT_COMMAND => {
+                self.lint_workspace().await;
+
+                Ok(None)
+            }
+            DOCTOR_COMMAND => Ok(Some(self.doctor().await)),
+            _ => Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                "Unknown command {}",
+                params.command
+            ))),
+        }
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+        let file_uri = params.text_document.uri;
+        let file_path = match file_uri.to_file_path() {
+            Ok(file_path) => file_path,
+            Err(_) => {
+                info!("Skipping formatting of {}, it's not a file", file_uri);
+                return Ok(None);
+            }
+        };
+        let root = self.file_root(&file_path);
+
+        let text = match self.documents.get(&file_uri) {
+            Some(text) => text.clone(),
+            None => std::fs::read_to_string(&file_path).unwrap_or_default(),
+        };
+
+        // Formatters in the formatter_priority order first, the rest sorted by
+        // plugin id, so the same formatter wins when several match.
+        let formatter_priority = self
+            .client_settings
+            .settings
+            .get("formatter_priority")
+            .map(|formatter_priority| split_list(&formatter_priority))
+            .unwrap_or_default();
+        let mut installed_plugins = self.plugins_for_root(&root);
+        installed_plugins.sort_by_key(|(id, _)| {
+            let priority = formatter_priority
+                .iter()
+                .position(|formatter| formatter == id)
+                .unwrap_or(usize::MAX);
+            (priority, id.clone())
+        });
+
+        for (id, settings) in installed_plugins {
+            let plugin = self.server_settings.available_plugins.get(&id).unwrap();
+            if !plugin.can_format()
+                || !matches_filetype(&file_path, &settings.root, &settings.filetypes)
+                || matches_exclude(&file_path, &settings.root, &settings.exclude)
+            {
                 continue;
             }
 
+            let settings = self.resolve_cmd(&id, settings, &file_path);
+
+            info!("Formatting {} with plugin {}", file_uri, id);
             self.client
-                .log_message(
-                    MessageType::ERROR,
-                    format!(
-                        "{} plugin is not installed or can't be executed.",
-                        plugin_id
-                    ),
+                .log_message(MessageType::LOG, format!("Formatting with plugin: {}", id))
+                .await;
+
+            let formatted = plugin
+                .format(
+                    settings,
+                    file_uri.clone(),
+                    text.clone(),
+                    self.client.clone(),
                 )
                 .await;
-        }
 
-        self.client
-            .log_message(MessageType::INFO, "checkmate initialized!")
-            .await;
-    }
+            // A failing formatter falls back to the next one.
+            if let Some(formatted) = formatted {
+                return Ok(Some(formatting_edits(&text, formatted)));
+            }
+        }
 
-    async fn hover(&self, _params: HoverParams) -> Result<Option<Hover>> {
         Ok(None)
     }
 
-    async fn code_action(&self, _params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
-        Ok(None)
+    async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        let file_uri = params.text_document.uri;
+        self.documents
+            .insert(file_uri.clone(), params.text_document.text);
+
+        // Plugins linting the file on disk on change wait for it to differ
+        // from what's linted now.
+        let content_hash = file_content_hash(&file_uri);
+        if let Some(content_hash) = content_hash {
+            self.content_hashes.insert(file_uri.clone(), content_hash);
+        }
+
+        // Diagnostics persisted for the same contents are shown while the
+        // fresh lint runs.
+        if self
+            .client_settings
+            .settings
+            .contains_key("persist_diagnostics")
+            && !self.diagnostics.contains_key(&file_uri)
+        {
+            let persisted = content_hash.and_then(|content_hash| {
+                persist::load(
+                    &project_root(&self.client_settings.settings),
+                    &file_uri,
+                    content_hash,
+                )
+            });
+            if let Some(persisted) = persisted {
+                self.diagnostics.insert(file_uri.clone(), persisted);
+                self.publish(file_uri.clone()).await;
+            }
+        }
+
+        self.lint(file_uri, "open").await;
     }
 
-    async fn goto_definition(
-        &self,
-        _params: GotoDefinitionParams,
-    ) -> Result<Option<GotoDefinitionResponse>> {
-        Ok(None)
+    async fn did_change(&self, mut params: DidChangeTextDocumentParams) {
+        let file_uri = params.text_document.uri;
+
+        // Full sync, the last change holds the whole document.
+        if let Some(change) = params.content_changes.pop() {
+            self.documents.insert(file_uri.clone(), change.text);
+        }
+
+        self.lint(file_uri, "change").await;
     }
 
-    async fn shutdown(&self) -> Result<()> {
-        Ok(())
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        for change in params.changes {
+            if change.typ == FileChangeType::DELETED {
+                continue;
+            }
+
+            // Closed files are linted when they're opened.
+            if !self.documents.contains_key(&change.uri) {
+                continue;
+            }
+
+            // The editor's own save, did_save lints it once it settles.
+            if self.last_saves.contains_key(&change.uri) {
+                continue;
+            }
+
+            // Saves from the editor are linted by did_save, only changed
+            // contents are linted here.
+            match file_content_hash(&change.uri) {
+                Some(content_hash)
+                    if self.content_hashes.get(&change.uri).map(|hash| *hash)
+                        != Some(content_hash) =>
+                {
+                    self.content_hashes.insert(change.uri.clone(), content_hash);
+                    self.linted_hashes.insert(change.uri.clone(), content_hash);
+                }
+                _ => continue,
+            }
+
+            info!("{} changed on disk, re-linting", change.uri);
+            self.lint(change.uri, "save").await;
+        }
     }
 
-    async fn did_change(&self, _params: DidChangeTextDocumentParams) {}
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        self.documents.remove(&params.text_document.uri);
+        self.last_saves.remove(&params.text_document.uri);
+
+        // Results for a closed file are no longer wanted.
+        self.lint_tokens.retain(|(uri, _), cancelled| {
+            if *uri != params.text_document.uri {
+                return true;
+            }
+
+            cancelled.cancel();
+            false
+        });
+    }
 
     async fn did_save(&self, params: DidSaveTextDocumentParams) {
-        let file_uri = params.text_document.uri.clone();
+        let file_uri = params.text_document.uri;
 
-        self.client
-            .log_message(MessageType::INFO, "Text saved, running linters...")
-            .await;
+        // Scratch buffers and remote files can't be linted.
+        if file_uri.to_file_path().is_err() {
+            info!("Skipping {}, it's not a file", file_uri);
+            return;
+        }
 
-        for (id, settings) in self.server_settings.installed_plugins.clone() {
-            let plugin = self.server_settings.available_plugins.get(&id).unwrap();
+        // Wait for the saves to settle, only the last one lints the final
+        // contents.
+        let saved_at = Instant::now();
+        self.last_saves.insert(file_uri.clone(), saved_at);
+        tokio::time::sleep(SAVE_SETTLE).await;
+        if self.last_saves.get(&file_uri).map(|last_save| *last_save) != Some(saved_at) {
+            info!("{} was saved again, skipping the earlier save", file_uri);
+            return;
+        }
+
+        // Saving without changes (e.g. format on save loops) reuses the
+        // last diagnostics instead of running the linters again.
+        let content_hash = file_content_hash(&file_uri);
+        let unchanged = content_hash.is_some()
+            && self.linted_hashes.get(&file_uri).map(|hash| *hash) == content_hash;
+        if let Some(content_hash) = content_hash {
+            self.content_hashes.insert(file_uri.clone(), content_hash);
+            self.linted_hashes.insert(file_uri.clone(), content_hash);
+        }
 
+        // With the hash recorded the watcher no longer mistakes this save
+        // for a change on disk.
+        self.last_saves
+            .remove_if(&file_uri, |_, last_save| *last_save == saved_at);
+
+        if unchanged {
             self.client
                 .log_message(
                     MessageType::LOG,
-                    format!("Running plugin: {}", plugin.get_plugin_id()),
+                    "File is unchanged, publishing the last diagnostics.",
                 )
                 .await;
+            self.publish(file_uri).await;
+            return;
+        }
 
-            // Validate filetypes.
-            if !settings.filetypes.contains(
-                &file_uri
-                    .to_file_path()
-                    .unwrap()
-                    .extension()
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .to_string(),
-            ) {
+        self.client
+            .log_message(MessageType::INFO, "Text saved, running linters...")
+            .await;
+
+        self.lint(file_uri.clone(), "save").await;
+
+        // Saving a trigger file (e.g. composer.json) re-lints a broader scope.
+        let file_name = file_uri
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .unwrap_or_default()
+            .to_string();
+
+        if file_name == IGNORE_FILE {
+            *self.server_settings.ignore.write().unwrap() =
+                load_ignore_file(&project_root(&self.client_settings.settings));
+        }
+        let scope = match self.client_settings.relint_triggers.get(&file_name) {
+            Some(scope) => scope.clone(),
+            None => return,
+        };
+
+        match &scope[..] {
+            "open" => {
                 self.client
                     .log_message(
-                        MessageType::ERROR,
-                        format!(
-                            "Invalid filetype, allowed filetypes for this plugin {} are: {:?}",
-                            id, settings.filetypes
-                        ),
+                        MessageType::INFO,
+                        format!("{} saved, re-linting open documents...", file_name),
                     )
                     .await;
 
-                continue;
-            }
+                let open_documents: Vec<Url> = self
+                    .documents
+                    .iter()
+                    .map(|document| document.key().clone())
+                    .filter(|uri| *uri != file_uri)
+                    .collect();
 
-            plugin
-                .run(
-                    settings,
-                    params.text_document.uri.clone(),
-                    self.client.clone(),
-                )
-                .await;
+                for uri in open_documents {
+                    self.lint(uri, "save").await;
+                }
+            }
+            _ => {
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!("Unknown re-lint scope {} for {}", scope, file_name),
+                    )
+                    .await;
+            }
         }
     }
 }
 
-fn parse_client_editor_settings(config: Vec<Value>) -> HashMap<String, PluginSetting> {
-    let mut editor_plugins: HashMap<String, PluginSetting> = HashMap::new();
-    for mut item in config {
-        if item.as_object_mut().is_none() {
-            continue;
+impl Lsp {
+    // Load the editor settings and install the configured plugins.
+    async fn configure(&self) {
+        // Parse editor settings.
+        let editor_settings_items = ConfigurationItem {
+            scope_uri: None,
+            section: Some("checkmate.plugins".to_string()),
+        };
+
+        // A failed fetch keeps the previous plugins, and their diagnostics.
+        let editor_settings = match self.client.configuration(vec![editor_settings_items]).await {
+            Ok(editor_settings) => editor_settings,
+            Err(e) => {
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!("Cant fetch code editor config: {}", e),
+                    )
+                    .await;
+                return;
+            }
+        };
+
+        // Editor settings take precedence over the environment, which takes
+        // precedence over the project file.
+        let mut editor_plugins =
+            project_plugin_settings(&project_root(&self.client_settings.settings));
+        editor_plugins.extend(env_plugin_settings());
+        editor_plugins.extend(parse_client_editor_settings(editor_settings));
+
+        // Results of the previous configuration can't be reused on save, the
+        // content hashes stay so focus and change lints still see what's on
+        // disk.
+        self.linted_hashes.clear();
+
+        // In strict mode a configured but missing tool is a visible error.
+        let strict_detection = self
+            .fetch_editor_setting("checkmate.strict_detection")
+            .await
+            .and_then(|strict_detection| strict_detection.as_bool())
+            .unwrap_or(false);
+        let mut missing_plugins = vec![];
+        let mut unknown_plugins = vec![];
+        let mut installed_plugins = HashMap::new();
+        let root = project_root(&self.client_settings.settings);
+
+        self.server_settings.configured_plugins.clear();
+        self.server_settings.folder_plugins.clear();
+        for (plugin_id, settings) in &editor_plugins {
+            self.server_settings
+                .configured_plugins
+                .insert(plugin_id.clone(), settings.clone());
         }
 
-        let settings_object = item.as_object_mut().expect("Settings are not an object.");
+        for (plugin_id, settings) in editor_plugins {
+            let plugin_discovered = self.server_settings.available_plugins.get(&plugin_id);
 
-        for id in settings_object.keys() {
-            let user_defined_settings_object = settings_object.get(id).unwrap().as_object();
+            if plugin_discovered.is_none() {
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!("{} plugin does not exist.", plugin_id),
+                    )
+                    .await;
+                info!("{} plugin does not exist.", plugin_id);
+
+                if self
+                    .server_settings
+                    .warned_unknown_plugins
+                    .insert(plugin_id.clone())
+                {
+                    unknown_plugins.push(plugin_id);
+                }
 
-            if user_defined_settings_object.is_none() {
-                editor_plugins.insert(id.to_owned(), PluginSetting::default());
                 continue;
             }
 
-            let user_defined_settings = user_defined_settings_object.unwrap();
-            let cmd = user_defined_settings
-                .get("cmd")
-                .unwrap_or(&Value::String("".to_string()))
-                .as_str()
-                .unwrap_or("")
-                .to_string();
-            let args = user_defined_settings
-                .get("args")
-                .unwrap_or(&Value::String("".to_string()))
-                .as_str()
-                .unwrap_or("")
-                .to_string();
-            let filetypes = user_defined_settings
-                .get("filetypes")
-                .unwrap_or(&Value::String("".to_string()))
-                .as_str()
-                .unwrap_or("")
-                .to_string();
+            let plugin = plugin_discovered.unwrap();
 
-            let mut args_vec = vec![];
-            args.split(' ').for_each(|i| {
-                args_vec.push(i.to_string());
-            });
+            if let Some(default_plugin_setting) =
+                plugin.is_installed(self.client_settings.settings.clone(), &settings)
+            {
+                self.client
+                    .log_message(
+                        MessageType::LOG,
+                        format!(
+                            "Plugin {} is installed, executable path is {}",
+                            plugin_id, default_plugin_setting.cmd
+                        ),
+                    )
+                    .await;
 
-            let mut filetypes_vec = vec![];
-            filetypes.split(',').for_each(|i| {
-                filetypes_vec.push(i.to_string());
-            });
+                let plugin_settings =
+                    merge_plugin_settings(default_plugin_setting, settings, &root);
 
-            editor_plugins.insert(
-                id.to_owned(),
-                PluginSetting {
-                    cmd,
-                    args: args_vec,
-                    filetypes: filetypes_vec,
-                },
+                installed_plugins.insert(plugin_id, plugin_settings);
+                continue;
+            }
+
+            let message = format!(
+                "{} plugin is not installed or can't be executed.",
+                plugin_id
+            );
+            self.client
+                .log_message(MessageType::ERROR, message.clone())
+                .await;
+
+            if strict_detection {
+                self.client.show_message(MessageType::ERROR, message).await;
+                missing_plugins.push(plugin_id);
+            }
+        }
+
+        // The new plugins replace the previous ones as a whole.
+        self.server_settings.installed_plugins.clear();
+        for (plugin_id, plugin_settings) in installed_plugins {
+            self.server_settings
+                .installed_plugins
+                .insert(plugin_id, plugin_settings);
+        }
+
+        if strict_detection {
+            self.publish_missing_plugins(missing_plugins).await;
+        }
+
+        // Most likely a typo in the settings, which is easy to miss in the log.
+        if !unknown_plugins.is_empty() {
+            unknown_plugins.sort();
+            let mut available_plugins: Vec<&str> = self
+                .server_settings
+                .available_plugins
+                .keys()
+                .map(String::as_str)
+                .collect();
+            available_plugins.sort();
+
+            self.client
+                .show_message(
+                    MessageType::WARNING,
+                    format!(
+                        "Unknown plugins in the settings: {}. Available plugins are: {}",
+                        unknown_plugins.join(", "),
+                        available_plugins.join(", ")
+                    ),
+                )
+                .await;
+        }
+
+        // Minimum interval between publishes, for clients which process
+        // diagnostics slowly.
+        let publish_interval_ms = self
+            .fetch_editor_setting("checkmate.publish_interval_ms")
+            .await
+            .and_then(|publish_interval_ms| publish_interval_ms.as_u64())
+            .unwrap_or(0);
+        self.publish_throttle
+            .set_interval(Duration::from_millis(publish_interval_ms));
+
+        // Cap the diagnostics published per file, 0 is unlimited.
+        let max_diagnostics = self
+            .fetch_editor_setting("checkmate.max_diagnostics")
+            .await
+            .and_then(|max_diagnostics| max_diagnostics.as_u64())
+            .unwrap_or(0);
+        if max_diagnostics > 0 {
+            self.client_settings
+                .settings
+                .insert("max_diagnostics".to_string(), max_diagnostics.to_string());
+        } else {
+            self.client_settings.settings.remove("max_diagnostics");
+        }
+
+        // Sort the diagnostics top to bottom, unless disabled.
+        let sort_diagnostics = self
+            .fetch_editor_setting("checkmate.sort_diagnostics")
+            .await
+            .and_then(|sort_diagnostics| sort_diagnostics.as_bool())
+            .unwrap_or(true);
+        if sort_diagnostics {
+            self.client_settings.settings.remove("unsorted_diagnostics");
+        } else {
+            self.client_settings
+                .settings
+                .insert("unsorted_diagnostics".to_string(), "true".to_string());
+        }
+
+        // Formatters to use first when several can format a file.
+        let formatter_priority = match self
+            .fetch_editor_setting("checkmate.formatter_priority")
+            .await
+        {
+            Some(Value::Array(ids)) => ids
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect(),
+            Some(Value::String(ids)) => split_list(&ids),
+            _ => vec![],
+        };
+        if formatter_priority.is_empty() {
+            self.client_settings.settings.remove("formatter_priority");
+        } else {
+            self.client_settings.settings.insert(
+                "formatter_priority".to_string(),
+                formatter_priority.join(","),
             );
         }
+
+        // A message with the counts after each plugin run, for editors
+        // without a good problems view.
+        let summary_notifications = self
+            .fetch_editor_setting("checkmate.summary_notifications")
+            .await
+            .and_then(|summary_notifications| summary_notifications.as_bool())
+            .unwrap_or(false);
+        if summary_notifications {
+            self.client_settings
+                .settings
+                .insert("summary_notifications".to_string(), "true".to_string());
+        } else {
+            self.client_settings
+                .settings
+                .remove("summary_notifications");
+        }
+
+        // Keep the last diagnostics on disk, to show them after a restart.
+        let persist_diagnostics = self
+            .fetch_editor_setting("checkmate.persist_diagnostics")
+            .await
+            .and_then(|persist_diagnostics| persist_diagnostics.as_bool())
+            .unwrap_or(false);
+        if persist_diagnostics {
+            // Once on startup, or when it's turned on.
+            if !self
+                .client_settings
+                .settings
+                .contains_key("persist_diagnostics")
+            {
+                persist::prune();
+            }
+            self.client_settings
+                .settings
+                .insert("persist_diagnostics".to_string(), "true".to_string());
+        } else {
+            self.client_settings.settings.remove("persist_diagnostics");
+        }
+
+        // Files which re-lint a broader scope when saved.
+        self.client_settings.relint_triggers.clear();
+        if let Some(Value::Object(relint_triggers)) =
+            self.fetch_editor_setting("checkmate.relint_triggers").await
+        {
+            for (file_name, scope) in relint_triggers {
+                if let Some(scope) = scope.as_str() {
+                    self.client_settings
+                        .relint_triggers
+                        .insert(file_name, scope.to_string());
+                }
+            }
+        }
     }
 
-    editor_plugins
+    // All available plugins, whether they're configured and installed, and
+    // the resolved cmd of the installed ones. Lets editors show a status view.
+    fn plugins_status(&self) -> Value {
+        let mut plugin_ids: Vec<&String> = self.server_settings.available_plugins.keys().collect();
+        plugin_ids.sort();
+
+        let plugins: Vec<Value> = plugin_ids
+            .into_iter()
+            .map(|plugin_id| {
+                let installed = self.server_settings.installed_plugins.get(plugin_id);
+
+                json!({
+                    "id": plugin_id,
+                    "configured": self.server_settings.configured_plugins.contains_key(plugin_id),
+                    "installed": installed.is_some(),
+                    "cmd": installed.map(|settings| settings.cmd.clone()),
+                })
+            })
+            .collect();
+
+        Value::Array(plugins)
+    }
+
+    // Check every configured plugin, show the findings and return them.
+    async fn doctor(&self) -> Value {
+        let mut plugin_ids: Vec<String> = self
+            .server_settings
+            .configured_plugins
+            .iter()
+            .map(|plugin| plugin.key().clone())
+            .collect();
+        plugin_ids.sort();
+
+        let mut report = vec![];
+        let mut lines = vec![];
+        for plugin_id in plugin_ids {
+            let known = self
+                .server_settings
+                .available_plugins
+                .contains_key(&plugin_id);
+            let installed = self
+                .server_settings
+                .installed_plugins
+                .get(&plugin_id)
+                .map(|settings| settings.clone());
+
+            let mut problems = vec![];
+            let mut version = None;
+            if !known {
+                problems.push("unknown plugin id".to_string());
+            } else if let Some(settings) = &installed {
+                version = tool_version(settings).await;
+                if version.is_none() {
+                    problems.push(format!("{} --version failed", settings.cmd));
+                }
+                problems.extend(filetype_problems(&settings.filetypes));
+            } else {
+                problems.push("binary not found or can't be executed".to_string());
+            }
+
+            let summary = match &installed {
+                Some(settings) if problems.is_empty() => format!(
+                    "ok, {} ({})",
+                    settings.cmd,
+                    version.clone().unwrap_or_default()
+                ),
+                _ => problems.join(", "),
+            };
+            lines.push(format!("{}: {}", plugin_id, summary));
+
+            report.push(json!({
+                "id": plugin_id,
+                "known": known,
+                "installed": installed.is_some(),
+                "cmd": installed.as_ref().map(|settings| settings.cmd.clone()),
+                "version": version,
+                "filetypes": installed.as_ref().map(|settings| settings.filetypes.clone()),
+                "problems": problems,
+            }));
+        }
+
+        if lines.is_empty() {
+            lines.push("No plugins are configured.".to_string());
+        }
+        self.client
+            .show_message(MessageType::INFO, lines.join("\n"))
+            .await;
+
+        Value::Array(report)
+    }
+
+    // Ask the client to notify about changes of any file in the workspace.
+    async fn register_file_watcher(&self) {
+        let options = DidChangeWatchedFilesRegistrationOptions {
+            watchers: vec![FileSystemWatcher {
+                glob_pattern: GlobPattern::String("**/*".to_string()),
+                kind: Some(WatchKind::Create | WatchKind::Change),
+            }],
+        };
+
+        let registration = Registration {
+            id: "checkmate-watched-files".to_string(),
+            method: "workspace/didChangeWatchedFiles".to_string(),
+            register_options: serde_json::to_value(options).ok(),
+        };
+
+        if let Err(e) = self.client.register_capability(vec![registration]).await {
+            error!("Cant register the file watcher: {}", e);
+        }
+    }
+
+    // Whether the file matches the .checkmateignore patterns.
+    fn is_ignored(&self, file_path: &Path) -> bool {
+        let ignore = self.server_settings.ignore.read().unwrap();
+
+        // The matcher panics on paths outside of the root.
+        !ignore.is_empty()
+            && file_path.starts_with(ignore.path())
+            && ignore
+                .matched_path_or_any_parents(file_path, false)
+                .is_ignore()
+    }
+
+    // Workspace folder holding the file, the deepest one for nested folders.
+    fn file_root(&self, file_path: &Path) -> String {
+        self.client_settings
+            .workspace_folders
+            .iter()
+            .map(|folder| folder.key().clone())
+            .filter(|root| file_path.starts_with(root))
+            .max_by_key(|root| root.len())
+            .unwrap_or_else(|| project_root(&self.client_settings.settings))
+    }
+
+    // Installed plugins for a workspace folder. Plugins are detected for
+    // each folder, so every project gets its own local binaries.
+    fn plugins_for_root(&self, root: &str) -> Vec<(String, PluginSetting)> {
+        let configured_plugins: Vec<(String, PluginSetting)> = self
+            .server_settings
+            .configured_plugins
+            .clone()
+            .into_iter()
+            .collect();
+
+        // Plugins which were missing are looked up again, so e.g. a
+        // `composer install` after startup is picked up.
+        if root == project_root(&self.client_settings.settings) {
+            for (plugin_id, settings) in configured_plugins {
+                if self
+                    .server_settings
+                    .installed_plugins
+                    .contains_key(&plugin_id)
+                {
+                    continue;
+                }
+
+                if let Some(plugin_settings) = self.detect_plugin(&plugin_id, settings, root) {
+                    self.server_settings
+                        .installed_plugins
+                        .insert(plugin_id, plugin_settings);
+                }
+            }
+
+            return self
+                .server_settings
+                .installed_plugins
+                .clone()
+                .into_iter()
+                .collect();
+        }
+
+        let mut plugins = vec![];
+        for (plugin_id, settings) in configured_plugins {
+            let key = (root.to_string(), plugin_id.clone());
+            let detected = self
+                .server_settings
+                .folder_plugins
+                .get(&key)
+                .map(|folder_plugin| folder_plugin.is_some())
+                .unwrap_or(false);
+            if !detected {
+                let folder_plugin = self.detect_plugin(&plugin_id, settings, root);
+                self.server_settings
+                    .folder_plugins
+                    .insert(key.clone(), folder_plugin);
+            }
+
+            if let Some(plugin_settings) = self.server_settings.folder_plugins.get(&key) {
+                if let Some(plugin_settings) = plugin_settings.clone() {
+                    plugins.push((plugin_id, plugin_settings));
+                }
+            }
+        }
+
+        plugins
+    }
+
+    // Use the local binary nearest to the file, walking up to the project
+    // root, e.g. in a monorepo where each package installs its own linter.
+    // Without one, a cmd which no longer exists is detected again. A cmd set
+    // by the user is kept.
+    fn resolve_cmd(
+        &self,
+        plugin_id: &str,
+        mut settings: PluginSetting,
+        file_path: &Path,
+    ) -> PluginSetting {
+        let cmd_overridden = self
+            .server_settings
+            .configured_plugins
+            .get(plugin_id)
+            .map(|configured| !configured.cmd.is_empty())
+            .unwrap_or(false);
+        let plugin = match self.server_settings.available_plugins.get(plugin_id) {
+            Some(plugin) if !cmd_overridden => plugin,
+            _ => return settings,
+        };
+
+        let local_binary = file_path
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(&settings.root))
+            .find_map(|dir| find_project_binary(&dir.to_string_lossy(), plugin.local_binaries()));
+
+        if let Some(local_binary) = local_binary {
+            settings.cmd = local_binary;
+            return settings;
+        }
+
+        if cmd_exists(&settings.cmd) {
+            return settings;
+        }
+
+        let configured = match self.server_settings.configured_plugins.get(plugin_id) {
+            Some(configured) => configured.clone(),
+            None => return settings,
+        };
+        if let Some(detected) = self.detect_plugin(plugin_id, configured, &settings.root) {
+            info!(
+                "{} no longer exists, running {} with {}",
+                settings.cmd, plugin_id, detected.cmd
+            );
+            settings.cmd = detected.cmd;
+        }
+
+        settings
+    }
+
+    fn detect_plugin(
+        &self,
+        plugin_id: &str,
+        settings: PluginSetting,
+        root: &str,
+    ) -> Option<PluginSetting> {
+        let plugin = self.server_settings.available_plugins.get(plugin_id)?;
+
+        let folder_settings = self.client_settings.settings.clone();
+        folder_settings.insert(
+            "root_uri".to_string(),
+            Url::from_file_path(root).ok()?.to_string(),
+        );
+
+        let default_plugin_setting = plugin.is_installed(folder_settings, &settings)?;
+        info!("Plugin {} found for {}", plugin_id, root);
+        Some(merge_plugin_settings(
+            default_plugin_setting,
+            settings,
+            root,
+        ))
+    }
+
+    // Handle the checkmate/didFocus notification, editors send it when a
+    // document gains focus. Re-lints the file when it was changed outside
+    // the editor since the last lint.
+    pub async fn did_focus(&self, params: DidFocusParams) {
+        let file_uri = params.text_document.uri;
+
+        let last_hash = match self.content_hashes.get(&file_uri) {
+            Some(last_hash) => *last_hash,
+            None => return,
+        };
+
+        let content_hash = match file_content_hash(&file_uri) {
+            Some(content_hash) => content_hash,
+            None => return,
+        };
+
+        if content_hash == last_hash {
+            return;
+        }
+
+        self.client
+            .log_message(
+                MessageType::INFO,
+                "File changed outside the editor, running linters...",
+            )
+            .await;
+
+        self.content_hashes.insert(file_uri.clone(), content_hash);
+        self.linted_hashes.insert(file_uri.clone(), content_hash);
+        self.lint(file_uri, "save").await;
+    }
+
+    // Run the matching installed plugins which run on the trigger against
+    // the file.
+    pub async fn lint(&self, file_uri: Url, trigger: &str) {
+        // E.g. untitled: buffers, the plugins need a file on disk.
+        let file_path = match file_uri.to_file_path() {
+            Ok(file_path) => file_path,
+            Err(_) => {
+                info!("Skipping lint of {}, it's not a file", file_uri);
+                return;
+            }
+        };
+
+        if self.is_ignored(&file_path) {
+            info!("Skipping lint of {}, it's in {}", file_uri, IGNORE_FILE);
+            return;
+        }
+
+        // Only a lint for the same trigger supersedes a running one, so e.g.
+        // linting on change doesn't cancel a slow lint on save.
+        let cancelled = CancellationToken::new();
+        if let Some(previous) = self
+            .lint_tokens
+            .insert((file_uri.clone(), trigger.to_string()), cancelled.clone())
+        {
+            previous.cancel();
+        }
+
+        // A workspace lint reports its own progress, and one per keystroke
+        // would only flicker.
+        let progress_token = match trigger {
+            WORKSPACE_TRIGGER | PROJECT_TRIGGER | "change" => None,
+            _ => {
+                self.progress_begin(format!("Linting {}", file_uri.path()))
+                    .await
+            }
+        };
+
+        // Plugins linting the file on disk only see a change once it's saved.
+        let disk_changed = trigger != "change"
+            || file_content_hash(&file_uri) != self.content_hashes.get(&file_uri).map(|hash| *hash);
+
+        let root = self.file_root(&file_path);
+        for (id, settings) in self.plugins_for_root(&root) {
+            let plugin = self.server_settings.available_plugins.get(&id).unwrap();
+
+            let runs = match trigger {
+                MANUAL_TRIGGER => true,
+                WORKSPACE_TRIGGER => !plugin.checks_whole_project(),
+                PROJECT_TRIGGER => plugin.checks_whole_project(),
+                _ => settings.run_on.iter().any(|run_on| run_on == trigger),
+            };
+            if !runs {
+                continue;
+            }
+
+            self.client
+                .log_message(
+                    MessageType::LOG,
+                    format!("Running plugin: {}", plugin.get_plugin_id()),
+                )
+                .await;
+
+            // Validate filetypes.
+            if !matches_filetype(&file_path, &settings.root, &settings.filetypes) {
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!(
+                            "Invalid filetype, allowed filetypes for this plugin {} are: {:?}",
+                            id, settings.filetypes
+                        ),
+                    )
+                    .await;
+
+                continue;
+            }
+
+            // Skip excluded paths.
+            if matches_exclude(&file_path, &settings.root, &settings.exclude) {
+                self.client
+                    .log_message(
+                        MessageType::LOG,
+                        format!(
+                            "Skipping plugin {}, the file matches an exclude rule: {:?}",
+                            id, settings.exclude
+                        ),
+                    )
+                    .await;
+
+                continue;
+            }
+
+            if let Some(progress_token) = &progress_token {
+                self.progress_report(progress_token, format!("Running {}", id))
+                    .await;
+            }
+
+            // Dropping a cancelled run kills the running process, so stale
+            // diagnostics never land after fresh ones.
+            let settings = self.resolve_cmd(&id, settings, &file_path);
+            let ignore_rules = settings.ignore_rules.clone();
+            let message_prefix = settings.message_prefix.replace("{id}", &id);
+            // E.g. "/home/me/project/", without a root nothing is stripped.
+            let stripped_root = match settings.strip_project_root && !settings.root.is_empty() {
+                true => format!("{}/", settings.root.trim_end_matches('/')),
+                false => "".to_string(),
+            };
+            let process_limit = self.server_settings.process_limit.read().unwrap().clone();
+            // The unsaved buffer is linted by the plugins reading stdin.
+            let text = match trigger {
+                "change" if plugin.stdin_command(&settings, &file_uri).is_some() => {
+                    self.documents.get(&file_uri).map(|text| text.clone())
+                }
+                _ => None,
+            };
+            if text.is_none() && !disk_changed {
+                info!("Skipping plugin {}, {} is unchanged on disk", id, file_uri);
+                continue;
+            }
+            let run = async {
+                let _permit = process_limit.acquire_owned().await;
+                match text {
+                    Some(text) => {
+                        plugin
+                            .run_stdin(settings, file_uri.clone(), text, self.client.clone())
+                            .await
+                    }
+                    None => {
+                        plugin
+                            .run(settings, file_uri.clone(), self.client.clone())
+                            .await
+                    }
+                }
+            };
+            let plugin_output = tokio::select! {
+                plugin_output = run => Some(plugin_output),
+                _ = cancelled.cancelled() => None,
+            };
+
+            let plugin_output = match plugin_output {
+                Some(plugin_output) if !cancelled.is_cancelled() => plugin_output,
+                _ => {
+                    self.client
+                        .log_message(MessageType::LOG, format!("Lint of {} cancelled.", file_uri))
+                        .await;
+                    break;
+                }
+            };
+
+            if let Some(mut plugin_output) = plugin_output {
+                // A clean run still has to clear the previous diagnostics.
+                plugin_output
+                    .diagnostics
+                    .entry(file_uri.clone())
+                    .or_default();
+
+                // Project scoped plugins report on other files as well, so
+                // files missing from this run no longer have any problems.
+                // Plugins analysing only the linted file, e.g. phpstan, only
+                // cover the files their last run for it reported on.
+                if plugin.is_project_scoped() {
+                    let reported_uris: HashSet<Url> =
+                        plugin_output.diagnostics.keys().cloned().collect();
+                    let covered_uris = self
+                        .reported_uris
+                        .insert((file_uri.clone(), id.clone()), reported_uris)
+                        .unwrap_or_default();
+                    let stale_uris: Vec<Url> = self
+                        .diagnostics
+                        .iter()
+                        .filter(|file_diagnostics| {
+                            file_diagnostics.value().contains_key(&id)
+                                && !plugin_output
+                                    .diagnostics
+                                    .contains_key(file_diagnostics.key())
+                                && (plugin.checks_whole_project()
+                                    || covered_uris.contains(file_diagnostics.key()))
+                        })
+                        .map(|file_diagnostics| file_diagnostics.key().clone())
+                        .collect();
+
+                    for uri in stale_uris {
+                        if let Some(mut file_diagnostics) = self.diagnostics.get_mut(&uri) {
+                            file_diagnostics.remove(&id);
+                        }
+                        self.publish(uri).await;
+                    }
+                }
+
+                for (uri, mut diagnostics) in plugin_output.diagnostics {
+                    diagnostics.retain(|diagnostic| !is_ignored_rule(diagnostic, &ignore_rules));
+
+                    for diagnostic in diagnostics.iter_mut() {
+                        diagnostic.source.get_or_insert_with(|| id.clone());
+                        if !stripped_root.is_empty() {
+                            diagnostic.message = diagnostic.message.replace(&stripped_root, "");
+                        }
+                        diagnostic.message.insert_str(0, &message_prefix);
+
+                        if let Some(NumberOrString::String(rule)) = &diagnostic.code {
+                            if diagnostic.code_description.is_none() {
+                                diagnostic.code_description =
+                                    plugin.rule_url(rule).map(|href| CodeDescription { href });
+                            }
+
+                            if diagnostic.tags.is_none() {
+                                diagnostic.tags = plugin.rule_tags(rule);
+                            }
+                        }
+                    }
+
+                    if uri == file_uri {
+                        self.show_summary(&id, &diagnostics).await;
+                    }
+
+                    self.diagnostics
+                        .entry(uri.clone())
+                        .or_default()
+                        .insert(id.clone(), diagnostics);
+                    self.publish(uri).await;
+                }
+            }
+        }
+
+        if self
+            .client_settings
+            .settings
+            .contains_key("persist_diagnostics")
+            && !cancelled.is_cancelled()
+            && trigger != "change"
+        {
+            let content_hash = file_content_hash(&file_uri);
+            if let (Some(content_hash), Some(file_diagnostics)) =
+                (content_hash, self.diagnostics.get(&file_uri))
+            {
+                persist::store(
+                    &project_root(&self.client_settings.settings),
+                    &file_uri,
+                    content_hash,
+                    &file_diagnostics,
+                );
+            }
+        }
+
+        // A newer lint cancels this one before replacing its token, so an
+        // uncancelled token is still this lint's own.
+        self.lint_tokens
+            .remove_if(&(file_uri.clone(), trigger.to_string()), |_, _| {
+                !cancelled.is_cancelled()
+            });
+
+        if let Some(progress_token) = progress_token {
+            self.progress_end(progress_token).await;
+        }
+    }
+
+    // Lint every file in the workspace folders which an installed plugin
+    // matches, to see all problems of a project at once.
+    async fn lint_workspace(&self) {
+        let roots: Vec<String> = self
+            .client_settings
+            .workspace_folders
+            .iter()
+            .map(|folder| folder.key().clone())
+            .collect();
+
+        let mut files = vec![];
+        let mut project_files = vec![];
+        for root in roots {
+            let plugins = self.plugins_for_root(&root);
+            let root_files: Vec<PathBuf> = workspace_files(Path::new(&root))
+                .into_iter()
+                .filter(|path| {
+                    plugins.iter().any(|(_, settings)| {
+                        matches_filetype(path, &settings.root, &settings.filetypes)
+                            && !matches_exclude(path, &settings.root, &settings.exclude)
+                    })
+                })
+                .collect();
+
+            // Plugins checking the whole project run once, for their first file.
+            for (id, settings) in &plugins {
+                let whole_project = self
+                    .server_settings
+                    .available_plugins
+                    .get(id)
+                    .map(|plugin| plugin.checks_whole_project())
+                    .unwrap_or(false);
+                if !whole_project {
+                    continue;
+                }
+
+                let first_file = root_files
+                    .iter()
+                    .find(|path| matches_filetype(path, &settings.root, &settings.filetypes));
+                if let Some(first_file) = first_file {
+                    project_files.push(first_file.clone());
+                }
+            }
+
+            files.extend(root_files);
+        }
+
+        // Nested workspace folders list the same files.
+        files.sort();
+        files.dedup();
+        project_files.sort();
+        project_files.dedup();
+
+        let mut lints: Vec<(Url, &str)> = vec![];
+        for path in files {
+            if let Ok(uri) = Url::from_file_path(path) {
+                lints.push((uri, WORKSPACE_TRIGGER));
+            }
+        }
+        for path in project_files {
+            if let Ok(uri) = Url::from_file_path(path) {
+                lints.push((uri, PROJECT_TRIGGER));
+            }
+        }
+
+        self.client
+            .log_message(
+                MessageType::INFO,
+                format!("Linting the workspace, {} runs...", lints.len()),
+            )
+            .await;
+
+        let progress_token = self
+            .progress_begin("Linting the workspace".to_string())
+            .await;
+        let total = lints.len();
+        let done = AtomicUsize::new(0);
+
+        // The process limit caps how many plugins run at once.
+        stream::iter(lints)
+            .for_each_concurrent(None, |(uri, trigger)| {
+                let progress_token = &progress_token;
+                let done = &done;
+                async move {
+                    self.lint(uri, trigger).await;
+
+                    let done = done.fetch_add(1, Ordering::SeqCst) + 1;
+                    if let Some(progress_token) = progress_token {
+                        self.progress_report(progress_token, format!("{}/{} linted", done, total))
+                            .await;
+                    }
+                }
+            })
+            .await;
+
+        if let Some(progress_token) = progress_token {
+            self.progress_end(progress_token).await;
+        }
+    }
+
+    // Start a work done progress, if the client supports it.
+    async fn progress_begin(&self, message: String) -> Option<NumberOrString> {
+        if !self
+            .client_settings
+            .settings
+            .contains_key("work_done_progress")
+        {
+            return None;
+        }
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_nanos())
+            .unwrap_or_default();
+        let token = NumberOrString::String(format!("checkmate/lint/{}", nanos));
+
+        self.client
+            .send_request::<request::WorkDoneProgressCreate>(WorkDoneProgressCreateParams {
+                token: token.clone(),
+            })
+            .await
+            .ok()?;
+
+        self.send_progress(
+            &token,
+            WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                title: "checkmate".to_string(),
+                cancellable: Some(false),
+                message: Some(message),
+                percentage: None,
+            }),
+        )
+        .await;
+
+        Some(token)
+    }
+
+    async fn progress_report(&self, token: &NumberOrString, message: String) {
+        self.send_progress(
+            token,
+            WorkDoneProgress::Report(WorkDoneProgressReport {
+                cancellable: Some(false),
+                message: Some(message),
+                percentage: None,
+            }),
+        )
+        .await;
+    }
+
+    async fn progress_end(&self, token: NumberOrString) {
+        self.send_progress(
+            &token,
+            WorkDoneProgress::End(WorkDoneProgressEnd {
+                message: Some("Done".to_string()),
+            }),
+        )
+        .await;
+    }
+
+    async fn send_progress(&self, token: &NumberOrString, progress: WorkDoneProgress) {
+        self.client
+            .send_notification::<notification::Progress>(ProgressParams {
+                token: token.clone(),
+                value: ProgressParamsValue::WorkDone(progress),
+            })
+            .await;
+    }
+
+    // Publish the diagnostics of all plugins for the file.
+    async fn publish(&self, uri: Url) {
+        let diagnostics: Vec<Diagnostic> = match self.diagnostics.get(&uri) {
+            Some(file_diagnostics) => file_diagnostics.values().flatten().cloned().collect(),
+            None => vec![],
+        };
+        let max_diagnostics = self
+            .client_settings
+            .settings
+            .get("max_diagnostics")
+            .and_then(|max_diagnostics| max_diagnostics.parse().ok())
+            .unwrap_or(0);
+        let mut diagnostics = dedup(diagnostics);
+        if !self
+            .client_settings
+            .settings
+            .contains_key("unsorted_diagnostics")
+        {
+            diagnostics = sort(diagnostics);
+        }
+        let diagnostics = truncate(diagnostics, max_diagnostics);
+
+        self.publish_throttle
+            .publish(&self.client, uri, diagnostics)
+            .await;
+    }
+
+    // Show the error and warning counts of a plugin run, when enabled.
+    async fn show_summary(&self, plugin_id: &str, diagnostics: &[Diagnostic]) {
+        if !self
+            .client_settings
+            .settings
+            .contains_key("summary_notifications")
+        {
+            return;
+        }
+
+        let count = |severity| {
+            diagnostics
+                .iter()
+                .filter(|diagnostic| diagnostic.severity == Some(severity))
+                .count()
+        };
+
+        self.client
+            .show_message(
+                MessageType::INFO,
+                format!(
+                    "{}: {} errors, {} warnings",
+                    plugin_id,
+                    count(DiagnosticSeverity::ERROR),
+                    count(DiagnosticSeverity::WARNING)
+                ),
+            )
+            .await;
+    }
+
+    // Report missing plugins as a persistent error on the project root.
+    async fn publish_missing_plugins(&self, missing_plugins: Vec<String>) {
+        let root_uri = match self.client_settings.settings.get("root_uri") {
+            Some(root_uri) => Url::parse(&root_uri).ok(),
+            None => None,
+        };
+        let root_uri = match root_uri {
+            Some(root_uri) => root_uri,
+            None => return,
+        };
+
+        let diagnostics = missing_plugins
+            .iter()
+            .map(|plugin_id| {
+                Diagnostic::new(
+                    Range::new(Position::new(0, 0), Position::new(0, 0)),
+                    Some(DiagnosticSeverity::ERROR),
+                    None,
+                    Some("checkmate".to_string()),
+                    format!(
+                        "{} plugin is configured, but it's not installed or can't be executed.",
+                        plugin_id
+                    ),
+                    None,
+                    None,
+                )
+            })
+            .collect();
+
+        self.diagnostics
+            .entry(root_uri.clone())
+            .or_default()
+            .insert("checkmate".to_string(), diagnostics);
+        self.publish(root_uri).await;
+    }
+
+    // Fetch a single section of the editor settings.
+    async fn fetch_editor_setting(&self, section: &str) -> Option<Value> {
+        let item = ConfigurationItem {
+            scope_uri: None,
+            section: Some(section.to_string()),
+        };
+
+        self.client
+            .configuration(vec![item])
+            .await
+            .ok()?
+            .into_iter()
+            .next()
+    }
+}
+
+fn default_max_concurrency() -> usize {
+    std::thread::available_parallelism()
+        .map(|parallelism| parallelism.get())
+        .unwrap_or(4)
+}
+
+// Files below the directory, leaving out hidden and dependency directories.
+fn workspace_files(dir: &Path) -> Vec<PathBuf> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+
+    let mut files = vec![];
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') {
+            continue;
+        }
+
+        let path = entry.path();
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => {
+                if WORKSPACE_SKIPPED_DIRS.contains(&&name[..]) {
+                    continue;
+                }
+
+                files.extend(workspace_files(&path));
+            }
+            Ok(file_type) if file_type.is_file() => files.push(path),
+            _ => {}
+        }
+    }
+
+    files
+}
+
+// Whether the command is an existing file, or found on the PATH.
+fn cmd_exists(cmd: &str) -> bool {
+    if cmd.contains('/') {
+        return Path::new(cmd).is_file();
+    }
+
+    find_executable(cmd).is_some()
+}
+
+// Hash of the file contents on disk.
+fn file_content_hash(uri: &Url) -> Option<u64> {
+    let contents = std::fs::read(uri.to_file_path().ok()?).ok()?;
+
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+// Plugins configured in a .checkmate.toml at the project root, so a repo can
+// commit its linter setup.
+fn project_plugin_settings(root: &str) -> HashMap<String, PluginSetting> {
+    let config = match std::fs::read_to_string(Path::new(root).join(".checkmate.toml")) {
+        Ok(config) => config,
+        Err(_) => return HashMap::new(),
+    };
+
+    let config: ProjectConfig = match toml::from_str(&config) {
+        Ok(config) => config,
+        Err(err) => {
+            error!("Can't parse .checkmate.toml: {}", err);
+            return HashMap::new();
+        }
+    };
+
+    config
+        .plugins
+        .into_iter()
+        .map(|(id, settings)| {
+            let plugin_settings = PluginSetting {
+                cmd: settings.cmd,
+                args: split_args(&settings.args),
+                filetypes: split_filetypes(&settings.filetypes),
+                exclude: split_list(&settings.exclude),
+                use_baseline: settings.use_baseline.unwrap_or(true),
+                config_path: settings.config_path,
+                retry_on_empty: settings.retry_on_empty,
+                compilation_database: settings.compilation_database,
+                severity_map: normalize_severity_map(settings.severity_map),
+                ignore_rules: split_list(&settings.ignore_rules),
+                env: settings.env,
+                run_on: split_run_on(&settings.run_on),
+                standard: parse_standard(&id, settings.standard),
+                analyse_paths: split_paths(&settings.analyse_paths),
+                message_prefix: parse_message_prefix(settings.message_prefix.as_ref()),
+                max_output_bytes: settings
+                    .max_output_bytes
+                    .unwrap_or(DEFAULT_MAX_OUTPUT_BYTES),
+                strip_project_root: settings.strip_project_root,
+                append_file: parse_append_file(&settings.append_file),
+                ..PluginSetting::default()
+            };
+
+            (id, plugin_settings)
+        })
+        .collect()
+}
+
+// Plugins configured through the CHECKMATE_CONFIG environment variable, a
+// JSON object in the same shape as the `checkmate.plugins` editor setting.
+fn env_plugin_settings() -> HashMap<String, PluginSetting> {
+    match std::env::var("CHECKMATE_CONFIG") {
+        Ok(config) => parse_env_config(&config),
+        Err(_) => HashMap::new(),
+    }
+}
+
+fn parse_env_config(config: &str) -> HashMap<String, PluginSetting> {
+    match serde_json::from_str::<Value>(config) {
+        Ok(config) => parse_client_editor_settings(vec![config]),
+        Err(err) => {
+            error!("Can't parse CHECKMATE_CONFIG: {}", err);
+            HashMap::new()
+        }
+    }
+}
+
+fn parse_client_editor_settings(config: Vec<Value>) -> HashMap<String, PluginSetting> {
+    let mut editor_plugins: HashMap<String, PluginSetting> = HashMap::new();
+    for mut item in config {
+        if item.as_object_mut().is_none() {
+            continue;
+        }
+
+        let settings_object = item.as_object_mut().expect("Settings are not an object.");
+
+        for id in settings_object.keys() {
+            let user_defined_settings_object = settings_object.get(id).unwrap().as_object();
+
+            if user_defined_settings_object.is_none() {
+                editor_plugins.insert(id.to_owned(), PluginSetting::default());
+                continue;
+            }
+
+            let user_defined_settings = user_defined_settings_object.unwrap();
+            let cmd = user_defined_settings
+                .get("cmd")
+                .unwrap_or(&Value::String("".to_string()))
+                .as_str()
+                .unwrap_or("")
+                .to_string();
+
+            let config_path = user_defined_settings
+                .get("config_path")
+                .unwrap_or(&Value::String("".to_string()))
+                .as_str()
+                .unwrap_or("")
+                .to_string();
+            let use_baseline = user_defined_settings
+                .get("use_baseline")
+                .unwrap_or(&Value::Bool(true))
+                .as_bool()
+                .unwrap_or(true);
+            let compilation_database = user_defined_settings
+                .get("compilation_database")
+                .unwrap_or(&Value::String("".to_string()))
+                .as_str()
+                .unwrap_or("")
+                .to_string();
+            let retry_on_empty = user_defined_settings
+                .get("retry_on_empty")
+                .unwrap_or(&Value::Bool(false))
+                .as_bool()
+                .unwrap_or(false);
+
+            let severity_map = match user_defined_settings.get("severity_map") {
+                Some(Value::Object(severity_map)) => severity_map
+                    .iter()
+                    .filter_map(|(native, severity)| {
+                        Some((native.clone(), severity.as_str()?.to_string()))
+                    })
+                    .collect(),
+                _ => HashMap::new(),
+            };
+
+            let strip_project_root = user_defined_settings
+                .get("strip_project_root")
+                .unwrap_or(&Value::Bool(false))
+                .as_bool()
+                .unwrap_or(false);
+
+            let max_output_bytes = user_defined_settings
+                .get("max_output_bytes")
+                .and_then(|max_output_bytes| max_output_bytes.as_u64())
+                .map(|max_output_bytes| max_output_bytes as usize)
+                .unwrap_or(DEFAULT_MAX_OUTPUT_BYTES);
+
+            let run_on = user_defined_settings
+                .get("run_on")
+                .unwrap_or(&Value::String("".to_string()))
+                .as_str()
+                .unwrap_or("")
+                .to_string();
+
+            let standard = user_defined_settings
+                .get("standard")
+                .map(|standard| standard.as_str().unwrap_or("").to_string());
+
+            let env = match user_defined_settings.get("env") {
+                Some(Value::Object(env)) => env
+                    .iter()
+                    .filter_map(|(name, value)| Some((name.clone(), value.as_str()?.to_string())))
+                    .collect(),
+                _ => HashMap::new(),
+            };
+
+            editor_plugins.insert(
+                id.to_owned(),
+                PluginSetting {
+                    cmd,
+                    args: list_setting(user_defined_settings, "args", split_args),
+                    filetypes: list_setting(user_defined_settings, "filetypes", split_filetypes),
+                    exclude: list_setting(user_defined_settings, "exclude", split_list),
+                    use_baseline,
+                    config_path,
+                    retry_on_empty,
+                    compilation_database,
+                    severity_map: normalize_severity_map(severity_map),
+                    ignore_rules: list_setting(user_defined_settings, "ignore_rules", split_list),
+                    env,
+                    run_on: split_run_on(&run_on),
+                    standard: parse_standard(id, standard),
+                    analyse_paths: list_setting(
+                        user_defined_settings,
+                        "analyse_paths",
+                        split_paths,
+                    ),
+                    message_prefix: parse_message_prefix(
+                        user_defined_settings.get("message_prefix"),
+                    ),
+                    max_output_bytes,
+                    strip_project_root,
+                    append_file: parse_append_file(
+                        user_defined_settings
+                            .get("append_file")
+                            .and_then(|append_file| append_file.as_str())
+                            .unwrap_or(""),
+                    ),
+                    ..PluginSetting::default()
+                },
+            );
+        }
+    }
+
+    editor_plugins
+}
+
+// A list setting given either as an array, or as a string which is split.
+fn list_setting(
+    settings: &serde_json::Map<String, Value>,
+    key: &str,
+    split: fn(&str) -> Vec<String>,
+) -> Vec<String> {
+    match settings.get(key) {
+        Some(Value::Array(items)) => items
+            .iter()
+            .filter_map(|item| item.as_str())
+            .filter(|item| !item.is_empty())
+            .map(|item| item.to_string())
+            .collect(),
+        Some(Value::String(value)) => split(value),
+        _ => vec![],
+    }
+}
+
+// Split the args like a shell, so a quoted value with spaces such as
+// --standard="Drupal Core" stays one argument.
+fn split_args(args: &str) -> Vec<String> {
+    match shlex::split(args) {
+        Some(args) => args,
+        None => {
+            error!("Unbalanced quotes in args {}, splitting on spaces", args);
+            args.split_whitespace().map(|i| i.to_string()).collect()
+        }
+    }
+}
+
+fn split_paths(paths: &str) -> Vec<String> {
+    paths.split_whitespace().map(|i| i.to_string()).collect()
+}
+
+// An empty setting gives no filetypes, rather than an empty one.
+fn split_filetypes(filetypes: &str) -> Vec<String> {
+    filetypes
+        .split(',')
+        .map(|i| i.trim().to_string())
+        .filter(|i| !i.is_empty())
+        .collect()
+}
+
+// Native severities are matched case insensitively, unknown target
+// severities are dropped.
+fn normalize_severity_map(severity_map: HashMap<String, String>) -> HashMap<String, String> {
+    severity_map
+        .into_iter()
+        .filter(|(native, severity)| {
+            if parse_severity(severity).is_none() {
+                error!(
+                    "Unknown severity {} for {} in severity_map",
+                    severity, native
+                );
+                return false;
+            }
+
+            true
+        })
+        .map(|(native, severity)| (native.to_lowercase(), severity))
+        .collect()
+}
+
+// `true` prefixes messages with "[id] ", a string is used as the template.
+fn parse_message_prefix(message_prefix: Option<&Value>) -> String {
+    match message_prefix {
+        Some(Value::Bool(true)) => "[{id}] ".to_string(),
+        Some(Value::String(message_prefix)) => message_prefix.clone(),
+        _ => "".to_string(),
+    }
+}
+
+// A standard which is set can't be empty.
+fn parse_standard(plugin_id: &str, standard: Option<String>) -> String {
+    match standard {
+        Some(standard) if standard.trim().is_empty() => {
+            error!("The standard for {} is empty, it's ignored", plugin_id);
+            "".to_string()
+        }
+        Some(standard) => standard.trim().to_string(),
+        None => "".to_string(),
+    }
+}
+
+// "last" or "first", anything else keeps the file last.
+fn parse_append_file(append_file: &str) -> AppendMode {
+    match append_file.trim() {
+        "" | "last" => AppendMode::Last,
+        "first" => AppendMode::First,
+        append_file => {
+            error!(
+                "Unknown append_file mode {}, the file goes last",
+                append_file
+            );
+            AppendMode::Last
+        }
+    }
+}
+
+// Unknown triggers are dropped, no triggers keeps the default ones.
+fn split_run_on(run_on: &str) -> Vec<String> {
+    let run_on: Vec<String> = split_list(run_on)
+        .into_iter()
+        .filter(|trigger| {
+            if !RUN_ON_TRIGGERS.contains(&&trigger[..]) {
+                error!("Unknown run_on trigger {}", trigger);
+                return false;
+            }
+
+            true
+        })
+        .collect();
+
+    if run_on.is_empty() {
+        return PluginSetting::default().run_on;
+    }
+
+    run_on
+}
+
+fn split_list(exclude: &str) -> Vec<String> {
+    exclude
+        .split(',')
+        .map(|i| i.trim().to_string())
+        .filter(|i| !i.is_empty())
+        .collect()
+}
+
+// Whether the diagnostic's rule is silenced by the plugin's ignore_rules.
+fn is_ignored_rule(diagnostic: &Diagnostic, ignore_rules: &[String]) -> bool {
+    let rule = match &diagnostic.code {
+        Some(NumberOrString::String(rule)) => rule.clone(),
+        Some(NumberOrString::Number(rule)) => rule.to_string(),
+        None => return false,
+    };
+
+    ignore_rules
+        .iter()
+        .any(|ignore_rule| match ignore_rule.strip_suffix('*') {
+            Some(prefix) => rule.starts_with(prefix),
+            None => rule == *ignore_rule,
+        })
+}
+
+// Zero width ranges cover their whole line, most linters only report a start.
+fn range_contains(range: &Range, position: &Position) -> bool {
+    if range.start == range.end {
+        return range.start.line == position.line;
+    }
+
+    range.start <= *position && *position <= range.end
+}
+
+fn hover_markdown(diagnostic: &Diagnostic) -> String {
+    let mut markdown = diagnostic.message.clone();
+
+    let code = match &diagnostic.code {
+        Some(NumberOrString::String(code)) => Some(code.clone()),
+        Some(NumberOrString::Number(code)) => Some(code.to_string()),
+        None => None,
+    };
+
+    if let Some(code) = code {
+        markdown.push_str(&format!("\n\n`{}`", code));
+        if let Some(source) = &diagnostic.source {
+            markdown.push_str(&format!(" ({})", source));
+        }
+    }
+
+    if let Some(code_description) = &diagnostic.code_description {
+        markdown.push_str(&format!("\n\n[Documentation]({})", code_description.href));
+    }
+
+    markdown
+}
+
+// Replace the whole document with the formatted text. An unchanged document
+// returns no edits, so the editor doesn't mark it as modified.
+fn formatting_edits(text: &str, formatted: String) -> Vec<TextEdit> {
+    if text == formatted {
+        return vec![];
+    }
+
+    let last_line = text.rsplit('\n').next().unwrap_or_default();
+    let end = Position {
+        line: text.matches('\n').count() as u32,
+        character: last_line.encode_utf16().count() as u32,
+    };
+
+    vec![TextEdit::new(
+        Range::new(Position::new(0, 0), end),
+        formatted,
+    )]
+}
+
+// Match the file against the filetypes. Plain entries are matched against
+// the end of the file name, so multi-dot filetypes like "blade.php" or "d.ts"
+// work, or the whole file name for files without an extension such as
+// "Dockerfile". Entries containing glob characters (e.g. "*.spec.js") are
+// matched against the path relative to the project root.
+fn matches_filetype(path: &Path, root: &str, filetypes: &[String]) -> bool {
+    let file_name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(file_name) => file_name,
+        None => return false,
+    };
+    let relative_path = path.strip_prefix(root).unwrap_or(path);
+
+    filetypes.iter().any(|filetype| {
+        if filetype.contains(['*', '?', '[']) {
+            return Pattern::new(filetype)
+                .map(|pattern| pattern.matches_path(relative_path))
+                .unwrap_or(false);
+        }
+
+        if file_name == filetype {
+            return true;
+        }
+
+        let filetype = filetype.trim_start_matches('.');
+        !filetype.is_empty() && file_name.ends_with(&format!(".{}", filetype))
+    })
+}
+
+// Check the project relative path, and each of its parent directories,
+// against the exclude globs so both "vendor" and "dist/**/*.js" work.
+fn matches_exclude(path: &Path, root: &str, exclude: &[String]) -> bool {
+    let relative_path = path.strip_prefix(root).unwrap_or(path);
+
+    exclude.iter().any(|exclude| match Pattern::new(exclude) {
+        Ok(pattern) => relative_path
+            .ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .any(|ancestor| pattern.matches_path(ancestor)),
+        Err(_) => false,
+    })
+}
+
+// Merge the user's settings over the plugin defaults, for a project root.
+fn merge_plugin_settings(
+    default_plugin_setting: PluginSetting,
+    settings: PluginSetting,
+    root: &str,
+) -> PluginSetting {
+    let mut plugin_settings = PluginSetting::default();
+
+    // CMD
+    if !settings.cmd.is_empty() {
+        plugin_settings.cmd.clone_from(&settings.cmd);
+    } else {
+        plugin_settings.cmd.clone_from(&default_plugin_setting.cmd);
+    }
+
+    // ARGS.
+    let mut plugin_args = default_plugin_setting.args.clone();
+    for arg in settings.args {
+        plugin_args.push(arg);
+    }
+    plugin_settings.args = plugin_args;
+
+    // Filetypes.
+    if !settings.filetypes.is_empty() {
+        let mut plugin_filetypes = default_plugin_setting.filetypes.clone();
+        for i in settings.filetypes {
+            plugin_filetypes.push(i);
+        }
+        plugin_settings.filetypes = plugin_filetypes;
+    } else {
+        plugin_settings
+            .filetypes
+            .clone_from(&default_plugin_setting.filetypes);
+    }
+
+    // Exclude.
+    plugin_settings.exclude = settings.exclude;
+
+    // Baseline.
+    plugin_settings.use_baseline = settings.use_baseline;
+
+    // Retry.
+    plugin_settings.retry_on_empty = settings.retry_on_empty;
+
+    // Compilation database.
+    plugin_settings.compilation_database = settings.compilation_database;
+
+    // Severity map.
+    plugin_settings.severity_map = settings.severity_map;
+
+    // Ignored rules.
+    plugin_settings.ignore_rules = settings.ignore_rules;
+
+    // Environment.
+    plugin_settings.env = settings.env;
+
+    // Triggers.
+    plugin_settings.run_on = settings.run_on;
+
+    // Coding standard.
+    plugin_settings.standard = settings.standard;
+
+    // Analysed paths.
+    plugin_settings.analyse_paths = settings.analyse_paths;
+
+    // Message prefix.
+    plugin_settings.message_prefix = settings.message_prefix;
+
+    // Output limit.
+    plugin_settings.max_output_bytes = settings.max_output_bytes;
+
+    // Project root in messages.
+    plugin_settings.strip_project_root = settings.strip_project_root;
+
+    // File position.
+    plugin_settings.append_file = settings.append_file;
+
+    // Working directory, set by the plugin.
+    plugin_settings.working_dir = default_plugin_setting.working_dir;
+
+    // Config.
+    plugin_settings.config_path = settings.config_path;
+    plugin_settings.root = root.to_string();
+
+    // Relative paths are resolved against the project root.
+    resolve_relative_paths(&mut plugin_settings, root);
+
+    plugin_settings
+}
+
+// First line of the tool's --version output, None when it can't be run.
+async fn tool_version(plugin_settings: &PluginSetting) -> Option<String> {
+    // A tool that waits on something shouldn't hang the command.
+    let output = tokio::time::timeout(
+        Duration::from_secs(5),
+        execute(
+            &plugin_settings.cmd,
+            vec!["--version".to_string()],
+            &plugin_settings.env,
+            &plugin_settings.working_dir,
+            plugin_settings.max_output_bytes,
+        ),
+    )
+    .await
+    .ok()?
+    .ok()?;
+
+    // Some tools print the version to stderr.
+    [&output.stdout, &output.stderr]
+        .iter()
+        .filter_map(|stream| str::from_utf8(stream).ok())
+        .flat_map(|stream| stream.lines())
+        .map(|line| line.trim().to_string())
+        .find(|line| !line.is_empty())
+}
+
+// Filetypes which can't match any file.
+fn filetype_problems(filetypes: &[String]) -> Vec<String> {
+    if filetypes.is_empty() {
+        return vec!["no filetypes, it never runs".to_string()];
+    }
+
+    filetypes
+        .iter()
+        .filter_map(|filetype| {
+            if filetype.contains(['*', '?', '[']) && Pattern::new(filetype).is_err() {
+                return Some(format!("filetype {} is not a valid glob", filetype));
+            }
+
+            if filetype.contains([' ', '/', ',']) && !filetype.contains(['*', '?', '[']) {
+                return Some(format!(
+                    "filetype {} doesn't look like an extension",
+                    filetype
+                ));
+            }
+
+            None
+        })
+        .collect()
+}
+
+// The features the server implements, a new handler needs its capability
+// here too or clients won't use it.
+fn server_capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        // Plugins lint the file on disk, so the content is only kept for
+        // detecting changes and formatting.
+        text_document_sync: Some(TextDocumentSyncCapability::Options(
+            TextDocumentSyncOptions {
+                open_close: Some(true),
+                change: Some(TextDocumentSyncKind::FULL),
+                save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+                ..TextDocumentSyncOptions::default()
+            },
+        )),
+        workspace: Some(WorkspaceServerCapabilities {
+            workspace_folders: Some(WorkspaceFoldersServerCapabilities {
+                supported: Some(true),
+                change_notifications: Some(OneOf::Left(true)),
+            }),
+            file_operations: None,
+        }),
+        // Diagnostic details with rule links.
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        execute_command_provider: Some(ExecuteCommandOptions {
+            commands: vec![
+                PLUGINS_COMMAND.to_string(),
+                LINT_COMMAND.to_string(),
+                WORKSPACE_LINT_COMMAND.to_string(),
+                DOCTOR_COMMAND.to_string(),
+            ],
+            work_done_progress_options: WorkDoneProgressOptions::default(),
+        }),
+        ..ServerCapabilities::default()
+    }
+}
+
+// Read the .checkmateignore at the root, a missing file ignores nothing.
+fn load_ignore_file(root: &str) -> Gitignore {
+    let path = Path::new(root).join(IGNORE_FILE);
+    if !path.is_file() {
+        return Gitignore::empty();
+    }
+
+    let (ignore, e) = Gitignore::new(&path);
+    if let Some(e) = e {
+        error!("Cant parse {}: {}", path.display(), e);
+    }
+
+    info!(
+        "Loaded {} patterns from {}",
+        ignore.num_ignores(),
+        path.display()
+    );
+    ignore
+}
+
+fn project_root(settings: &DashMap<String, String>) -> String {
+    settings
+        .get("root_uri")
+        .map(|root_uri| root_uri.to_string())
+        .unwrap_or_default()
+        .replace("file://", "")
+}
+
+// The first workspace folder, else the directory the server was started in.
+fn fallback_root_uri(params: &InitializeParams) -> Option<Url> {
+    params
+        .workspace_folders
+        .as_ref()
+        .and_then(|folders| folders.first())
+        .map(|folder| folder.uri.clone())
+        .or_else(|| {
+            std::env::current_dir()
+                .ok()
+                .and_then(|dir| Url::from_file_path(dir).ok())
+        })
+}
+
+fn resolve_relative_paths(plugin_settings: &mut PluginSetting, root: &str) {
+    // A bare command name (e.g. "phpcs") is left for the PATH lookup.
+    if plugin_settings.cmd.contains('/') && !plugin_settings.cmd.contains('{') {
+        if let Some(cmd) = resolve_path(&plugin_settings.cmd, root) {
+            plugin_settings.cmd = cmd;
+        }
+    }
+
+    if let Some(config_path) = resolve_path(&plugin_settings.config_path, root) {
+        plugin_settings.config_path = config_path;
+    }
+
+    if let Some(compilation_database) = resolve_path(&plugin_settings.compilation_database, root) {
+        plugin_settings.compilation_database = compilation_database;
+    }
+
+    for path in plugin_settings.analyse_paths.iter_mut() {
+        if let Some(resolved) = resolve_path(path, root) {
+            *path = resolved;
+        }
+    }
+
+    for arg in plugin_settings.args.iter_mut() {
+        if let Some(resolved) = resolve_arg(arg, root) {
+            *arg = resolved;
+        }
+    }
+}
+
+// Only explicitly relative args are paths, globs, flag values and bare words
+// (e.g. "src/**/*.js", "--ext=.js" or "src") are passed on as they are.
+fn resolve_arg(arg: &str, root: &str) -> Option<String> {
+    // Flags can carry a path as their value, e.g. --config=./phpstan.neon.
+    if arg.starts_with('-') {
+        let (flag, value) = arg.split_once('=')?;
+        if !is_relative(value) {
+            return None;
+        }
+
+        return resolve_path(value, root).map(|value| format!("{}={}", flag, value));
+    }
+
+    if !is_relative(arg) {
+        return None;
+    }
+
+    resolve_path(arg, root)
+}
+
+fn is_relative(value: &str) -> bool {
+    value.starts_with("./") || value.starts_with("../")
+}
+
+fn resolve_path(value: &str, root: &str) -> Option<String> {
+    let path = Path::new(value);
+    if value.is_empty() || path.is_absolute() {
+        return None;
+    }
+
+    let relative = path.strip_prefix("./").unwrap_or(path);
+    Some(Path::new(root).join(relative).to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    use async_trait::async_trait;
+    use tower_lsp::LspService;
+
+    use crate::plugins::PluginOutput;
+    use crate::test_support::{temp_dir, write_file};
+
+    // Stands in for a tool, recording the files it was asked to lint or
+    // format. Runs report the queued outputs in turn, then nothing.
+    #[derive(Default, Clone)]
+    struct RecordingPlugin {
+        runs: Arc<Mutex<Vec<Url>>>,
+        formats: Arc<Mutex<Vec<Url>>>,
+        outputs: Arc<Mutex<VecDeque<PluginOutput>>>,
+        formatted: Option<String>,
+        project_scoped: bool,
+        // Time a run takes, e.g. to cancel it while it runs.
+        delay: Duration,
+        // Reads the document from stdin, recording the texts.
+        stdin: bool,
+        stdin_runs: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl RecordingPlugin {
+        fn runs(&self) -> Vec<Url> {
+            self.runs.lock().unwrap().clone()
+        }
+
+        fn queue(&self, output: HashMap<Url, Vec<Diagnostic>>) {
+            self.outputs.lock().unwrap().push_back(PluginOutput {
+                diagnostics: output,
+            });
+        }
+
+        fn formats(&self) -> Vec<Url> {
+            self.formats.lock().unwrap().clone()
+        }
+
+        fn stdin_runs(&self) -> Vec<String> {
+            self.stdin_runs.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl Plugin for RecordingPlugin {
+        fn get_plugin_id(&self) -> &str {
+            "recording"
+        }
+
+        fn is_installed(
+            &self,
+            _settings: DashMap<String, String>,
+            _user_settings: &PluginSetting,
+        ) -> Option<PluginSetting> {
+            None
+        }
+
+        fn is_project_scoped(&self) -> bool {
+            self.project_scoped
+        }
+
+        async fn run(
+            &self,
+            _plugin_settings: PluginSetting,
+            uri: Url,
+            _client: Client,
+        ) -> Option<PluginOutput> {
+            self.runs.lock().unwrap().push(uri.clone());
+            tokio::time::sleep(self.delay).await;
+            let output = self.outputs.lock().unwrap().pop_front();
+            Some(output.unwrap_or_else(|| PluginOutput {
+                diagnostics: HashMap::from([(uri, vec![])]),
+            }))
+        }
+
+        fn stdin_command(
+            &self,
+            _plugin_settings: &PluginSetting,
+            _uri: &Url,
+        ) -> Option<Vec<String>> {
+            self.stdin.then(Vec::new)
+        }
+
+        async fn run_stdin(
+            &self,
+            _plugin_settings: PluginSetting,
+            uri: Url,
+            text: String,
+            _client: Client,
+        ) -> Option<PluginOutput> {
+            self.stdin_runs.lock().unwrap().push(text);
+            Some(PluginOutput {
+                diagnostics: HashMap::from([(uri, vec![])]),
+            })
+        }
+
+        fn can_format(&self) -> bool {
+            self.formatted.is_some()
+        }
+
+        async fn format(
+            &self,
+            _plugin_settings: PluginSetting,
+            uri: Url,
+            _text: String,
+            _client: Client,
+        ) -> Option<String> {
+            self.formats.lock().unwrap().push(uri);
+            self.formatted.clone()
+        }
+    }
+
+    // A server for the project root with the plugins available, without a
+    // client. It's never initialized, so requests to the client fail and only
+    // log messages are sent, which are thrown away.
+    fn test_server(root: &Path, plugins: &[(&str, &RecordingPlugin)]) -> LspService<Lsp> {
+        let root_uri = Url::from_file_path(root).unwrap().to_string();
+        let plugins: Vec<(String, RecordingPlugin)> = plugins
+            .iter()
+            .map(|(id, plugin)| (id.to_string(), (*plugin).clone()))
+            .collect();
+
+        let (service, socket) = LspService::new(move |client| {
+            let mut lsp = Lsp::new(client);
+            lsp.client_settings
+                .settings
+                .insert("root_uri".to_string(), root_uri);
+            for (id, plugin) in plugins {
+                lsp.server_settings
+                    .available_plugins
+                    .insert(id, Box::new(plugin));
+            }
+            lsp
+        });
+        tokio::spawn(socket.for_each(|_| async {}));
+
+        service
+    }
+
+    // Install an available plugin for the files with the filetypes, running
+    // on save and open.
+    fn install(lsp: &Lsp, id: &str, root: &Path, filetypes: &[&str]) {
+        lsp.server_settings.installed_plugins.insert(
+            id.to_string(),
+            PluginSetting {
+                cmd: "sh".to_string(),
+                filetypes: filetypes
+                    .iter()
+                    .map(|filetype| filetype.to_string())
+                    .collect(),
+                root: root.to_string_lossy().to_string(),
+                ..PluginSetting::default()
+            },
+        );
+    }
+
+    #[test]
+    fn resolves_explicitly_relative_args() {
+        let mut settings = PluginSetting {
+            cmd: "bin/eslint".to_string(),
+            config_path: "config/eslint.json".to_string(),
+            args: vec![
+                "./src".to_string(),
+                "../shared".to_string(),
+                "--config=./config/eslint.json".to_string(),
+            ],
+            ..PluginSetting::default()
+        };
+        resolve_relative_paths(&mut settings, "/project");
+
+        assert_eq!(settings.cmd, "/project/bin/eslint");
+        assert_eq!(settings.config_path, "/project/config/eslint.json");
+        assert_eq!(
+            settings.args,
+            vec![
+                "/project/src",
+                "/project/../shared",
+                "--config=/project/config/eslint.json"
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_other_args_alone() {
+        let args = vec![
+            "src/**/*.js".to_string(),
+            "--ext=.js".to_string(),
+            "--format=json".to_string(),
+            "--rulesdir=rules/custom".to_string(),
+            "analyse".to_string(),
+            "src".to_string(),
+            "{file}".to_string(),
+            "/etc/phpcs.xml".to_string(),
+        ];
+        let mut settings = PluginSetting {
+            cmd: "phpcs".to_string(),
+            args: args.clone(),
+            ..PluginSetting::default()
+        };
+        resolve_relative_paths(&mut settings, "/project");
+
+        assert_eq!(settings.cmd, "phpcs");
+        assert_eq!(settings.args, args);
+    }
+
+    fn filetypes(filetypes: &[&str]) -> Vec<String> {
+        filetypes
+            .iter()
+            .map(|filetype| filetype.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn matches_compound_extensions() {
+        let root = "/project";
+        let blade = filetypes(&["blade.php"]);
+        let declarations = filetypes(&["d.ts"]);
+
+        assert!(matches_filetype(
+            Path::new("/project/resources/views/component.blade.php"),
+            root,
+            &blade
+        ));
+        assert!(!matches_filetype(
+            Path::new("/project/src/Foo.php"),
+            root,
+            &blade
+        ));
+        assert!(matches_filetype(
+            Path::new("/project/types/index.d.ts"),
+            root,
+            &declarations
+        ));
+        assert!(!matches_filetype(
+            Path::new("/project/src/index.ts"),
+            root,
+            &declarations
+        ));
+        assert!(matches_filetype(
+            Path::new("/project/src/styles.module.css"),
+            root,
+            &filetypes(&["css"])
+        ));
+        assert!(matches_filetype(
+            Path::new("/project/Dockerfile"),
+            root,
+            &filetypes(&["Dockerfile"])
+        ));
+    }
+
+    #[test]
+    fn matches_plain_extensions_and_globs() {
+        let root = "/project";
+        let specs = filetypes(&["*.spec.js"]);
+
+        assert!(matches_filetype(
+            Path::new("/project/src/app.js"),
+            root,
+            &filetypes(&["js"])
+        ));
+        assert!(!matches_filetype(
+            Path::new("/project/src/app.jsx"),
+            root,
+            &filetypes(&["js"])
+        ));
+        assert!(matches_filetype(
+            Path::new("/project/src/app.spec.js"),
+            root,
+            &specs
+        ));
+        assert!(!matches_filetype(
+            Path::new("/project/src/app.js"),
+            root,
+            &specs
+        ));
+        assert!(matches_filetype(
+            Path::new("/project/db/migrations/001_init.sql"),
+            root,
+            &filetypes(&["**/migrations/*.sql"])
+        ));
+    }
+
+    #[test]
+    fn formatted_document_has_no_edits() {
+        let text = "<?php\n\necho 'ok';\n";
+
+        assert!(formatting_edits(text, text.to_string()).is_empty());
+    }
+
+    #[test]
+    fn replaces_the_whole_document() {
+        let edits = formatting_edits("a  =  1\nb = 2", "a = 1\nb = 2\n".to_string());
+
+        assert_eq!(
+            edits,
+            vec![TextEdit::new(
+                Range::new(Position::new(0, 0), Position::new(1, 5)),
+                "a = 1\nb = 2\n".to_string()
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn formats_with_the_prioritized_formatter() {
+        let root = temp_dir("formatter-priority");
+        let file = write_file(&root, "app.js", "let a=1\n");
+        let eslint = RecordingPlugin {
+            formatted: Some("let a = 1;\n".to_string()),
+            ..RecordingPlugin::default()
+        };
+        let prettier = RecordingPlugin {
+            formatted: Some("let a = 1\n".to_string()),
+            ..RecordingPlugin::default()
+        };
+        let service = test_server(&root, &[("eslint", &eslint), ("prettier", &prettier)]);
+        let lsp = service.inner();
+        install(lsp, "eslint", &root, &["js"]);
+        install(lsp, "prettier", &root, &["js"]);
+        let uri = Url::from_file_path(&file).unwrap();
+        let params = DocumentFormattingParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            options: FormattingOptions::default(),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+        };
+
+        // Without a priority the plugin id decides.
+        let edits = lsp.formatting(params.clone()).await.unwrap().unwrap();
+        assert_eq!(edits[0].new_text, "let a = 1;\n");
+        assert_eq!(eslint.formats(), vec![uri.clone()]);
+        assert!(prettier.formats().is_empty());
+
+        lsp.client_settings.settings.insert(
+            "formatter_priority".to_string(),
+            "prettier,eslint".to_string(),
+        );
+        let edits = lsp.formatting(params).await.unwrap().unwrap();
+        assert_eq!(edits[0].new_text, "let a = 1\n");
+        assert_eq!(prettier.formats(), vec![uri.clone()]);
+        assert_eq!(eslint.formats(), vec![uri]);
+    }
+
+    #[tokio::test]
+    async fn saving_a_trigger_file_relints_open_documents() {
+        let root = temp_dir("relint-triggers");
+        let composer = write_file(&root, "composer.json", "{}");
+        let foo = write_file(&root, "src/Foo.php", "<?php\n");
+        let bar = write_file(&root, "src/Bar.php", "<?php\n");
+        let phpcs = RecordingPlugin::default();
+        let service = test_server(&root, &[("phpcs", &phpcs)]);
+        let lsp = service.inner();
+        install(lsp, "phpcs", &root, &["php"]);
+        lsp.client_settings
+            .relint_triggers
+            .insert("composer.json".to_string(), "open".to_string());
+        let composer = Url::from_file_path(&composer).unwrap();
+        let foo = Url::from_file_path(&foo).unwrap();
+        let bar = Url::from_file_path(&bar).unwrap();
+        for uri in [&composer, &foo, &bar] {
+            lsp.documents.insert(uri.clone(), String::new());
+        }
+
+        lsp.did_save(DidSaveTextDocumentParams {
+            text_document: TextDocumentIdentifier::new(composer),
+            text: None,
+        })
+        .await;
+
+        let mut runs = phpcs.runs();
+        runs.sort();
+        let mut expected = vec![foo, bar];
+        expected.sort();
+        assert_eq!(runs, expected);
+    }
+
+    #[tokio::test]
+    async fn finished_lints_drop_their_token() {
+        let root = temp_dir("lint-tokens");
+        let uri = Url::from_file_path(root.join("src/Foo.php")).unwrap();
+        let phpcs = RecordingPlugin::default();
+        let service = test_server(&root, &[("phpcs", &phpcs)]);
+        let lsp = service.inner();
+        install(lsp, "phpcs", &root, &["php"]);
+
+        lsp.lint(uri.clone(), "save").await;
+
+        assert_eq!(phpcs.runs(), vec![uri]);
+        assert!(lsp.lint_tokens.is_empty());
+    }
+
+    #[tokio::test]
+    async fn cancelled_lints_publish_nothing() {
+        let root = temp_dir("cancelled-lint");
+        let uri = Url::from_file_path(root.join("src/Foo.php")).unwrap();
+        let phpcs = RecordingPlugin {
+            delay: Duration::from_millis(200),
+            ..RecordingPlugin::default()
+        };
+        let problem = Diagnostic::new_simple(Range::default(), "Missing doc comment".to_string());
+        phpcs.queue(HashMap::from([(uri.clone(), vec![problem])]));
+        let service = test_server(&root, &[("phpcs", &phpcs)]);
+        let lsp = service.inner();
+        install(lsp, "phpcs", &root, &["php"]);
+        // Publishes after this one wait for the interval, so they can be seen.
+        lsp.publish_throttle.set_interval(Duration::from_secs(60));
+        let other = Url::from_file_path(root.join("src/Bar.php")).unwrap();
+        lsp.publish(other.clone()).await;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        lsp.publish(other.clone()).await;
+
+        let lint = lsp.lint(uri.clone(), "save");
+        let cancel = async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            lsp.lint_tokens
+                .get(&(uri.clone(), "save".to_string()))
+                .unwrap()
+                .cancel();
+        };
+        tokio::join!(lint, cancel);
+
+        assert_eq!(phpcs.runs(), vec![uri.clone()]);
+        assert!(!lsp.diagnostics.contains_key(&uri));
+        assert!(lsp.publish_throttle.is_pending(&other));
+        assert!(!lsp.publish_throttle.is_pending(&uri));
+    }
+
+    #[tokio::test]
+    async fn watcher_leaves_the_editor_save_to_did_save() {
+        let root = temp_dir("watched-save");
+        let file = write_file(&root, "src/Foo.php", "<?php\n");
+        let phpcs = RecordingPlugin::default();
+        let service = test_server(&root, &[("phpcs", &phpcs)]);
+        let lsp = service.inner();
+        install(lsp, "phpcs", &root, &["php"]);
+        let uri = Url::from_file_path(&file).unwrap();
+        lsp.documents.insert(uri.clone(), "<?php\n".to_string());
+
+        // The watcher reports the save while it's settling.
+        let save = lsp.did_save(DidSaveTextDocumentParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            text: None,
+        });
+        let watched = async {
+            tokio::time::sleep(SAVE_SETTLE / 4).await;
+            lsp.did_change_watched_files(DidChangeWatchedFilesParams {
+                changes: vec![FileEvent::new(uri.clone(), FileChangeType::CHANGED)],
+            })
+            .await;
+        };
+        tokio::join!(save, watched);
+
+        assert_eq!(phpcs.runs(), vec![uri.clone()]);
+        assert!(lsp.last_saves.is_empty());
+
+        // Later changes on disk are linted again.
+        std::fs::write(&file, "<?php\n\necho 'changed';\n").unwrap();
+        lsp.did_change_watched_files(DidChangeWatchedFilesParams {
+            changes: vec![FileEvent::new(uri.clone(), FileChangeType::CHANGED)],
+        })
+        .await;
+        assert_eq!(phpcs.runs(), vec![uri.clone(), uri]);
+    }
+
+    #[tokio::test]
+    async fn linting_on_change_sees_the_unsaved_buffer() {
+        let root = temp_dir("run-on-change");
+        let file = write_file(&root, "src/app.js", "let a = 1;\n");
+        let eslint = RecordingPlugin {
+            stdin: true,
+            ..RecordingPlugin::default()
+        };
+        let tsc = RecordingPlugin::default();
+        let service = test_server(&root, &[("eslint", &eslint), ("tsc", &tsc)]);
+        let lsp = service.inner();
+        for id in ["eslint", "tsc"] {
+            install(lsp, id, &root, &["js"]);
+            lsp.server_settings
+                .installed_plugins
+                .get_mut(id)
+                .unwrap()
+                .run_on = vec!["change".to_string()];
+        }
+        let uri = Url::from_file_path(&file).unwrap();
+        lsp.content_hashes
+            .insert(uri.clone(), file_content_hash(&uri).unwrap());
+
+        lsp.documents
+            .insert(uri.clone(), "let a = 1;\nlet b".to_string());
+        lsp.lint(uri.clone(), "change").await;
+        assert_eq!(eslint.stdin_runs(), vec!["let a = 1;\nlet b"]);
+        assert!(eslint.runs().is_empty());
+        assert!(tsc.runs().is_empty());
+
+        // E.g. saved by another program.
+        std::fs::write(&file, "let a = 2;\n").unwrap();
+        lsp.lint(uri.clone(), "change").await;
+        assert_eq!(tsc.runs(), vec![uri]);
+    }
+
+    #[tokio::test]
+    async fn change_waits_for_the_opened_file_to_change_on_disk() {
+        let root = temp_dir("change-after-open");
+        let file = write_file(&root, "src/app.ts", "let a = 1;\n");
+        let tsc = RecordingPlugin::default();
+        let service = test_server(&root, &[("tsc", &tsc)]);
+        let lsp = service.inner();
+        install(lsp, "tsc", &root, &["ts"]);
+        lsp.server_settings
+            .installed_plugins
+            .get_mut("tsc")
+            .unwrap()
+            .run_on = vec!["change".to_string()];
+        let uri = Url::from_file_path(&file).unwrap();
+
+        lsp.did_open(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(
+                uri.clone(),
+                "typescript".to_string(),
+                1,
+                "let a = 1;\n".to_string(),
+            ),
+        })
+        .await;
+        lsp.did_change(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(uri.clone(), 2),
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: "let a = 1;\nlet b".to_string(),
+            }],
+        })
+        .await;
+
+        assert!(tsc.runs().is_empty());
+    }
+
+    #[tokio::test]
+    async fn project_scoped_runs_clear_files_no_longer_reported() {
+        let root = temp_dir("project-scoped");
+        let foo = Url::from_file_path(root.join("src/Foo.php")).unwrap();
+        let bar = Url::from_file_path(root.join("src/Bar.php")).unwrap();
+        let problem = Diagnostic::new_simple(Range::default(), "Undefined method".to_string());
+        let phpstan = RecordingPlugin {
+            project_scoped: true,
+            ..RecordingPlugin::default()
+        };
+        phpstan.queue(HashMap::from([
+            (foo.clone(), vec![problem.clone()]),
+            (bar.clone(), vec![problem.clone()]),
+        ]));
+        phpstan.queue(HashMap::from([(foo.clone(), vec![problem])]));
+        let service = test_server(&root, &[("phpstan", &phpstan)]);
+        let lsp = service.inner();
+        install(lsp, "phpstan", &root, &["php"]);
+
+        lsp.lint(foo.clone(), "save").await;
+        assert_eq!(lsp.diagnostics.get(&bar).unwrap()["phpstan"].len(), 1);
+
+        lsp.lint(foo.clone(), "save").await;
+        assert!(!lsp.diagnostics.get(&bar).unwrap().contains_key("phpstan"));
+        assert_eq!(lsp.diagnostics.get(&foo).unwrap()["phpstan"].len(), 1);
+    }
+
+    #[tokio::test]
+    async fn project_scoped_runs_keep_files_they_did_not_cover() {
+        let root = temp_dir("project-scoped-coverage");
+        let foo = Url::from_file_path(root.join("src/Foo.php")).unwrap();
+        let bar = Url::from_file_path(root.join("src/Bar.php")).unwrap();
+        let problem = Diagnostic::new_simple(Range::default(), "Undefined method".to_string());
+        let phpstan = RecordingPlugin {
+            project_scoped: true,
+            ..RecordingPlugin::default()
+        };
+        phpstan.queue(HashMap::from([(foo.clone(), vec![problem.clone()])]));
+        phpstan.queue(HashMap::from([(bar.clone(), vec![problem])]));
+        let service = test_server(&root, &[("phpstan", &phpstan)]);
+        let lsp = service.inner();
+        install(lsp, "phpstan", &root, &["php"]);
+
+        lsp.lint(foo.clone(), "save").await;
+        lsp.lint(bar.clone(), "save").await;
+
+        assert_eq!(lsp.diagnostics.get(&foo).unwrap()["phpstan"].len(), 1);
+        assert_eq!(lsp.diagnostics.get(&bar).unwrap()["phpstan"].len(), 1);
+    }
+
+    #[tokio::test]
+    async fn strict_detection_publishes_missing_plugins() {
+        let root = temp_dir("strict-detection");
+        let service = test_server(&root, &[]);
+        let lsp = service.inner();
+
+        lsp.publish_missing_plugins(vec!["phpcs".to_string()]).await;
+
+        let root_uri = Url::from_file_path(&root).unwrap();
+        let diagnostics = &lsp.diagnostics.get(&root_uri).unwrap()["checkmate"];
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert!(diagnostics[0]
+            .message
+            .starts_with("phpcs plugin is configured"));
+    }
+
+    #[tokio::test]
+    async fn failed_settings_fetch_keeps_the_plugins() {
+        let root = temp_dir("failed-fetch");
+        let uri = Url::from_file_path(root.join("src/Foo.php")).unwrap();
+        let phpcs = RecordingPlugin::default();
+        let service = test_server(&root, &[("phpcs", &phpcs)]);
+        let lsp = service.inner();
+        install(lsp, "phpcs", &root, &["php"]);
+        let problem = Diagnostic::new_simple(Range::default(), "Missing doc comment".to_string());
+        lsp.diagnostics.insert(
+            uri.clone(),
+            HashMap::from([("phpcs".to_string(), vec![problem])]),
+        );
+
+        // The client isn't initialized, so the configuration request fails.
+        lsp.configure().await;
+
+        assert!(lsp.server_settings.installed_plugins.contains_key("phpcs"));
+        assert_eq!(lsp.diagnostics.get(&uri).unwrap()["phpcs"].len(), 1);
+    }
+
+    #[test]
+    fn ignores_exact_and_prefixed_rules() {
+        let with_code = |code: NumberOrString| Diagnostic {
+            code: Some(code),
+            ..Diagnostic::default()
+        };
+        let ignore_rules = vec![
+            "no-console".to_string(),
+            "Generic.Commenting.*".to_string(),
+            "1005".to_string(),
+        ];
+
+        let ignored = |code: &str| {
+            is_ignored_rule(
+                &with_code(NumberOrString::String(code.to_string())),
+                &ignore_rules,
+            )
+        };
+        assert!(ignored("no-console"));
+        assert!(!ignored("no-console-log"));
+        assert!(ignored("Generic.Commenting.DocComment.Missing"));
+        assert!(!ignored("Generic.Files.LineLength"));
+        assert!(is_ignored_rule(
+            &with_code(NumberOrString::Number(1005)),
+            &ignore_rules
+        ));
+        assert!(!is_ignored_rule(&Diagnostic::default(), &ignore_rules));
+    }
+
+    #[tokio::test]
+    async fn initializes_without_a_root_uri() {
+        let root = temp_dir("no-root-uri");
+        let folder = Url::from_file_path(&root).unwrap();
+        let (service, socket) = LspService::new(Lsp::new);
+        tokio::spawn(socket.for_each(|_| async {}));
+        let lsp = service.inner();
+
+        lsp.initialize(InitializeParams {
+            workspace_folders: Some(vec![WorkspaceFolder {
+                uri: folder.clone(),
+                name: "project".to_string(),
+            }]),
+            ..InitializeParams::default()
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            project_root(&lsp.client_settings.settings),
+            root.to_string_lossy()
+        );
+
+        let current_dir = Url::from_file_path(std::env::current_dir().unwrap()).unwrap();
+        assert_eq!(
+            fallback_root_uri(&InitializeParams::default()),
+            Some(current_dir)
+        );
+        assert_eq!(project_root(&DashMap::new()), "");
+    }
+
+    #[test]
+    fn splits_quoted_args() {
+        assert_eq!(
+            split_args(r#"--standard=PSR12 --ignore="vendor/*,tests/fixtures" '--report=json'"#),
+            vec![
+                "--standard=PSR12",
+                "--ignore=vendor/*,tests/fixtures",
+                "--report=json"
+            ]
+        );
+        assert_eq!(
+            split_args(r#"--exclude "My Documents/legacy""#),
+            vec!["--exclude", "My Documents/legacy"]
+        );
+        // Unbalanced quotes fall back to splitting on spaces.
+        assert_eq!(
+            split_args(r#"--name "unclosed"#),
+            vec!["--name", "\"unclosed"]
+        );
+    }
+
+    #[test]
+    fn empty_settings_give_no_args_or_filetypes() {
+        assert!(split_args("").is_empty());
+        assert!(split_args("   ").is_empty());
+        assert!(split_filetypes("").is_empty());
+        assert!(split_filetypes(" , ").is_empty());
+        assert_eq!(split_filetypes("php, inc"), vec!["php", "inc"]);
+    }
+
+    #[test]
+    fn parses_the_append_mode() {
+        assert_eq!(parse_append_file("first"), AppendMode::First);
+        assert_eq!(parse_append_file("last"), AppendMode::Last);
+        assert_eq!(parse_append_file(""), AppendMode::Last);
+        // A file name is placed with {file}, not with append_file.
+        assert_eq!(parse_append_file("{file}"), AppendMode::Last);
+    }
+
+    #[test]
+    fn parses_the_env_config() {
+        let plugins = parse_env_config(
+            r#"{"phpcs": {"args": "--standard=PSR12", "filetypes": "php,inc"}, "eslint": true}"#,
+        );
+
+        let mut ids: Vec<&String> = plugins.keys().collect();
+        ids.sort();
+        assert_eq!(ids, vec!["eslint", "phpcs"]);
+        assert_eq!(plugins["phpcs"].args, vec!["--standard=PSR12"]);
+        assert_eq!(plugins["phpcs"].filetypes, vec!["php", "inc"]);
+        assert!(parse_env_config("{not json").is_empty());
+    }
+
+    #[tokio::test]
+    async fn reconfiguring_relints_unchanged_saves() {
+        let root = temp_dir("unchanged-saves");
+        let file = write_file(&root, "src/Foo.php", "<?php\n");
+        let phpcs = RecordingPlugin::default();
+        let service = test_server(&root, &[("phpcs", &phpcs)]);
+        let lsp = service.inner();
+        install(lsp, "phpcs", &root, &["php"]);
+        let uri = Url::from_file_path(&file).unwrap();
+        let save = || DidSaveTextDocumentParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            text: None,
+        };
+
+        lsp.did_save(save()).await;
+        lsp.did_save(save()).await;
+        assert_eq!(phpcs.runs(), vec![uri.clone()]);
+
+        // As configure() does, the hashes of what's on disk stay.
+        lsp.linted_hashes.clear();
+        lsp.did_save(save()).await;
+        assert_eq!(phpcs.runs(), vec![uri.clone(), uri.clone()]);
+        assert!(lsp.content_hashes.contains_key(&uri));
+    }
+
+    #[tokio::test]
+    async fn focus_relints_files_changed_on_disk() {
+        let root = temp_dir("did-focus");
+        let file = write_file(&root, "src/Foo.php", "<?php\n");
+        let phpcs = RecordingPlugin::default();
+        let service = test_server(&root, &[("phpcs", &phpcs)]);
+        let lsp = service.inner();
+        install(lsp, "phpcs", &root, &["php"]);
+        let uri = Url::from_file_path(&file).unwrap();
+        let focus = || DidFocusParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+        };
+        lsp.content_hashes
+            .insert(uri.clone(), file_content_hash(&uri).unwrap());
+
+        lsp.did_focus(focus()).await;
+        assert!(phpcs.runs().is_empty());
+
+        // E.g. a git checkout.
+        std::fs::write(&file, "<?php\n\necho 'checked out';\n").unwrap();
+        lsp.did_focus(focus()).await;
+        assert_eq!(phpcs.runs(), vec![uri.clone()]);
+    }
 }
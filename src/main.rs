@@ -1,25 +1,102 @@
+use std::path::PathBuf;
+
 use clap::Parser;
+use flexi_logger::{FileSpec, Logger, LoggerHandle};
+use log::info;
+use tokio::net::TcpListener;
 use tower_lsp::LspService;
 use tower_lsp::Server;
 
-use crate::lsp::{ClientSettings, Lsp, ServerSettings};
+use crate::lsp::Lsp;
 
+mod diagnostics;
 mod lsp;
+mod persist;
 mod plugins;
+#[cfg(test)]
+mod test_support;
+mod throttle;
 
 #[derive(Parser)]
-struct Cli {}
+struct Cli {
+    /// Log level, e.g. error, info or debug. Logging is off unless this or
+    /// --log-file is set.
+    #[arg(long)]
+    log_level: Option<String>,
+
+    /// Write the logs to this file instead of stderr.
+    #[arg(long)]
+    log_file: Option<PathBuf>,
+
+    /// Serve over a TCP socket on this address (e.g. 127.0.0.1:9257) instead
+    /// of stdio.
+    #[arg(long)]
+    listen: Option<String>,
+}
+
+// Start the logger, the handle has to be kept for file logging to work.
+fn start_logger(cli: &Cli) -> Option<LoggerHandle> {
+    if cli.log_level.is_none() && cli.log_file.is_none() {
+        return None;
+    }
+
+    let level = cli.log_level.as_deref().unwrap_or("info");
+    let logger = match Logger::try_with_str(level) {
+        Ok(logger) => logger,
+        Err(e) => {
+            eprintln!("Invalid log level {}: {}", level, e);
+            return None;
+        }
+    };
+
+    let logger = match &cli.log_file {
+        Some(log_file) => match FileSpec::try_from(log_file) {
+            Ok(file_spec) => logger.log_to_file(file_spec).append(),
+            Err(e) => {
+                eprintln!("Invalid log file {}: {}", log_file.display(), e);
+                return None;
+            }
+        },
+        None => logger,
+    };
+
+    match logger.start() {
+        Ok(handle) => Some(handle),
+        Err(e) => {
+            eprintln!("Cant start the logger: {}", e);
+            None
+        }
+    }
+}
 
 #[tokio::main]
 async fn main() {
-    let stdin = tokio::io::stdin();
-    let stdout = tokio::io::stdout();
-
-    let (service, socket) = LspService::build(|client| Lsp {
-        client,
-        client_settings: ClientSettings::new(),
-        server_settings: ServerSettings::new(),
-    })
-    .finish();
-    Server::new(stdin, stdout, socket).serve(service).await;
+    let cli = Cli::parse();
+    let _logger = start_logger(&cli);
+
+    let (service, socket) = LspService::build(Lsp::new)
+        .custom_method("checkmate/didFocus", Lsp::did_focus)
+        .finish();
+
+    match cli.listen {
+        // Serve a single client over TCP, e.g. for attaching a debugger.
+        Some(addr) => {
+            let listener = TcpListener::bind(&addr)
+                .await
+                .unwrap_or_else(|e| panic!("Cant listen on {}: {}", addr, e));
+            info!("Listening on {}", addr);
+
+            let (stream, _) = listener
+                .accept()
+                .await
+                .expect("Cant accept the client connection.");
+            let (read, write) = tokio::io::split(stream);
+            Server::new(read, write, socket).serve(service).await;
+        }
+        None => {
+            let stdin = tokio::io::stdin();
+            let stdout = tokio::io::stdout();
+            Server::new(stdin, stdout, socket).serve(service).await;
+        }
+    }
 }
@@ -0,0 +1,153 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    collections::HashMap,
+    fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use log::{error, info};
+use serde_derive::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{Diagnostic, Url};
+
+// On-disk copy of the last diagnostics of a file, so they can be shown right
+// away after a server restart. Entries are only used for the same contents.
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PersistedDiagnostics {
+    content_hash: u64,
+    diagnostics: HashMap<String, Vec<Diagnostic>>,
+}
+
+// Entries not written for this long are removed on startup.
+const MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+fn hash(value: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+// The temp directory is shared, so the entries are kept apart per user.
+fn base_dir() -> PathBuf {
+    let user = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_default();
+
+    std::env::temp_dir().join(format!("checkmate-lsp-{}", user))
+}
+
+fn cache_path(root: &str, uri: &Url) -> PathBuf {
+    base_dir()
+        .join(format!("{:x}", hash(root)))
+        .join(format!("{:x}.json", hash(uri.as_str())))
+}
+
+// Store the diagnostics of each plugin for the file contents.
+pub fn store(
+    root: &str,
+    uri: &Url,
+    content_hash: u64,
+    diagnostics: &HashMap<String, Vec<Diagnostic>>,
+) {
+    let path = cache_path(root, uri);
+    let persisted = PersistedDiagnostics {
+        content_hash,
+        diagnostics: diagnostics.clone(),
+    };
+
+    let result = path
+        .parent()
+        .map(fs::create_dir_all)
+        .unwrap_or(Ok(()))
+        .and_then(|_| fs::write(&path, serde_json::to_vec(&persisted).unwrap_or_default()));
+    if let Err(e) = result {
+        error!("Can't persist the diagnostics of {}: {}", uri, e);
+    }
+}
+
+// Load the stored diagnostics, when they're for the same file contents.
+pub fn load(root: &str, uri: &Url, content_hash: u64) -> Option<HashMap<String, Vec<Diagnostic>>> {
+    let persisted = fs::read(cache_path(root, uri)).ok()?;
+    let persisted: PersistedDiagnostics = serde_json::from_slice(&persisted).ok()?;
+
+    if persisted.content_hash != content_hash {
+        return None;
+    }
+
+    Some(persisted.diagnostics)
+}
+
+// Remove the entries of files which weren't linted for a while.
+pub fn prune() {
+    prune_dir(&base_dir(), MAX_AGE);
+}
+
+fn prune_dir(dir: &Path, max_age: Duration) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            prune_dir(&path, max_age);
+            // Only succeeds once the root has no entries left.
+            let _ = fs::remove_dir(&path);
+            continue;
+        }
+
+        let expired = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_some_and(|age| age > max_age);
+        if expired {
+            info!("Removing persisted diagnostics {}", path.display());
+            let _ = fs::remove_file(&path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{temp_dir, write_file};
+
+    #[test]
+    fn keeps_the_entries_apart_per_root() {
+        let uri = Url::parse("file:///shared/src/Foo.php").unwrap();
+        let diagnostics = HashMap::from([("phpcs".to_string(), vec![Diagnostic::default()])]);
+
+        store("/project-a", &uri, 1, &diagnostics);
+
+        assert_eq!(load("/project-a", &uri, 1), Some(diagnostics));
+        assert_eq!(load("/project-a", &uri, 2), None);
+        assert_eq!(load("/project-b", &uri, 1), None);
+        assert!(cache_path("/project-a", &uri).starts_with(base_dir()));
+        let _ = fs::remove_dir_all(cache_path("/project-a", &uri).parent().unwrap());
+    }
+
+    #[test]
+    fn prunes_old_entries() {
+        let dir = temp_dir("persist-prune");
+        let old = write_file(&dir, "1f/old.json", "{}");
+        let fresh = write_file(&dir, "2e/fresh.json", "{}");
+        let two_weeks_ago = SystemTime::now() - Duration::from_secs(14 * 24 * 60 * 60);
+        fs::File::options()
+            .write(true)
+            .open(&old)
+            .unwrap()
+            .set_modified(two_weeks_ago)
+            .unwrap();
+
+        prune_dir(&dir, MAX_AGE);
+
+        assert!(!old.exists());
+        assert!(!dir.join("1f").exists());
+        assert!(fresh.exists());
+    }
+}
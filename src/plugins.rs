@@ -1,18 +1,91 @@
+use std::{
+    collections::HashMap,
+    env,
+    io::ErrorKind,
+    path::Path,
+    process::{Output, Stdio},
+};
+
 use async_trait::async_trait;
 use dashmap::DashMap;
-use tower_lsp::lsp_types::{DiagnosticSeverity, Url};
+use log::{error, info};
+use serde::de::DeserializeOwned;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tower_lsp::lsp_types::{
+    Diagnostic, DiagnosticSeverity, DiagnosticTag, MessageType, Position, Url,
+};
 use tower_lsp::Client;
 
+pub mod ameba;
+pub mod biome;
+pub mod black;
+pub mod cargo_check;
+pub mod cargo_common;
+pub mod clang_tidy;
+pub mod dart;
 pub mod eslint;
+pub mod gofmt;
+pub mod hadolint;
+pub mod markuplint;
+pub mod php_cs_fixer;
 pub mod phpcs;
+pub mod phpmd;
 pub mod phpstan;
+pub mod prettier;
+pub mod revive;
+pub mod rubocop_format;
+pub mod sqlfluff_format;
 pub mod stylelint;
+pub mod tsc;
+pub mod vale;
+
+// Output kept per stream of a tool by default, 16MB is far more than a
+// report normally takes.
+pub const DEFAULT_MAX_OUTPUT_BYTES: usize = 16 * 1024 * 1024;
+
+// Where the file goes in the args, unless an arg places it with {file}.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum AppendMode {
+    #[default]
+    Last,
+    First,
+}
 
 #[derive(Debug, Clone)]
 pub struct PluginSetting {
     pub cmd: String,
     pub args: Vec<String>,
     pub filetypes: Vec<String>,
+    pub exclude: Vec<String>,
+    pub use_baseline: bool,
+    pub config_path: String,
+    // Re-run once when the tool transiently prints nothing.
+    pub retry_on_empty: bool,
+    // Path to compile_commands.json, or the directory holding it.
+    pub compilation_database: String,
+    // Native tool severity to the reported severity, e.g. "warning" to "error".
+    pub severity_map: HashMap<String, String>,
+    // Rules to silence, exact ids or prefixes ending in `*`.
+    pub ignore_rules: Vec<String>,
+    // Extra environment variables for the tool.
+    pub env: HashMap<String, String>,
+    // Project root the plugin runs in.
+    pub root: String,
+    // Events the plugin runs on: save, open and change.
+    pub run_on: Vec<String>,
+    // PHPCS coding standard, e.g. "PSR12".
+    pub standard: String,
+    // Paths PHPStan analyses instead of the saved file.
+    pub analyse_paths: Vec<String>,
+    // Prepended to each message, {id} is replaced with the plugin id.
+    pub message_prefix: String,
+    // Output kept from the tool, per stream. The rest is dropped.
+    pub max_output_bytes: usize,
+    // Remove the project root from paths in the messages.
+    pub strip_project_root: bool,
+    pub append_file: AppendMode,
+    // Directory the tool runs in, the server's when empty.
+    pub working_dir: String,
 }
 
 impl Default for PluginSetting {
@@ -21,28 +94,30 @@ impl Default for PluginSetting {
             cmd: "".to_string(),
             args: Vec::new(),
             filetypes: Vec::new(),
+            exclude: Vec::new(),
+            use_baseline: true,
+            config_path: "".to_string(),
+            retry_on_empty: false,
+            compilation_database: "".to_string(),
+            severity_map: HashMap::new(),
+            ignore_rules: Vec::new(),
+            env: HashMap::new(),
+            root: "".to_string(),
+            run_on: vec!["save".to_string(), "open".to_string()],
+            standard: "".to_string(),
+            analyse_paths: Vec::new(),
+            message_prefix: "".to_string(),
+            max_output_bytes: DEFAULT_MAX_OUTPUT_BYTES,
+            strip_project_root: false,
+            append_file: AppendMode::Last,
+            working_dir: "".to_string(),
         }
     }
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct PluginOutput {
-    pub messages: Vec<PluginLineOutput>,
-}
-
-#[derive(Debug, Clone)]
-pub struct PluginLineOutput {
-    pub position: Position,
-    pub text: String,
-    pub severity: DiagnosticSeverity,
-}
-
-#[derive(Debug, Clone, Eq, Hash, PartialEq)]
-pub struct Position {
-    pub line: u32,
-    pub column: u32,
-    pub line_end: u32,
-    pub column_end: u32,
+    pub diagnostics: HashMap<Url, Vec<Diagnostic>>,
 }
 
 #[async_trait]
@@ -52,13 +127,717 @@ pub trait Plugin {
 
     // Check is the plugin is installed and can be executed.
     // Return the plugin settings if its installed.
-    fn is_installed(&self, settings: DashMap<String, String>) -> Option<PluginSetting>;
+    fn is_installed(
+        &self,
+        settings: DashMap<String, String>,
+        user_settings: &PluginSetting,
+    ) -> Option<PluginSetting>;
 
-    // Run plugin and return an output.
+    // Run plugin and return an output. The default runs the command and
+    // parses its output for the linted file, plugins reporting on other
+    // files or talking to the client replace it.
     async fn run(
         &self,
         plugin_settings: PluginSetting,
         uri: Url,
         client: Client,
-    ) -> Option<PluginOutput>;
+    ) -> Option<PluginOutput> {
+        let id = self.get_plugin_id();
+        let args = self.command(&plugin_settings, &uri);
+
+        client
+            .log_message(
+                MessageType::LOG,
+                format!("Running {} with command {}", id, plugin_settings.cmd),
+            )
+            .await;
+
+        let output = execute_plugin(&plugin_settings, args).await;
+        self.plugin_output(&plugin_settings, uri, output, &client)
+            .await
+    }
+
+    // Lint the unsaved text, passed on stdin, for plugins with a
+    // stdin_command. Returns None for the others.
+    async fn run_stdin(
+        &self,
+        plugin_settings: PluginSetting,
+        uri: Url,
+        text: String,
+        client: Client,
+    ) -> Option<PluginOutput> {
+        let id = self.get_plugin_id();
+        let args = self.stdin_command(&plugin_settings, &uri)?;
+
+        client
+            .log_message(
+                MessageType::LOG,
+                format!(
+                    "Running {} on stdin with command {}",
+                    id, plugin_settings.cmd
+                ),
+            )
+            .await;
+
+        let output = run_with_stdin(
+            &plugin_settings.cmd,
+            args,
+            &plugin_settings.env,
+            &plugin_settings.working_dir,
+            &text,
+            plugin_settings.max_output_bytes,
+        )
+        .await;
+        self.plugin_output(&plugin_settings, uri, output, &client)
+            .await
+    }
+
+    // Parse the output of a run into the diagnostics of the linted file.
+    async fn plugin_output(
+        &self,
+        plugin_settings: &PluginSetting,
+        uri: Url,
+        output: std::io::Result<Output>,
+        client: &Client,
+    ) -> Option<PluginOutput> {
+        let id = self.get_plugin_id();
+        let output = match output {
+            Ok(output) => output,
+            Err(e) => {
+                log_execute_error(client, &plugin_settings.cmd, e).await;
+                return None;
+            }
+        };
+
+        let diagnostics = match self.parse(plugin_settings, &uri, &output.stdout, &output.stderr) {
+            Ok(diagnostics) => diagnostics,
+            Err(e) => {
+                let message = format!("{} returned error: {}", id, e);
+                error!("{}", message);
+                client.log_message(MessageType::ERROR, message).await;
+                return None;
+            }
+        };
+
+        let mut plugin_output = PluginOutput::default();
+        plugin_output.diagnostics.insert(uri, diagnostics);
+
+        client
+            .log_message(MessageType::LOG, format!("{} ended", id))
+            .await;
+        Some(plugin_output)
+    }
+
+    // Arguments for the linted file, the placeholders substituted and the
+    // file appended unless the args place it.
+    fn command(&self, plugin_settings: &PluginSetting, uri: &Url) -> Vec<String> {
+        let file = uri.to_string().replace("file://", "");
+        build_args(plugin_settings, &file)
+    }
+
+    // Arguments to lint text passed on stdin, the real path given so the
+    // tool still finds the project config. None when the tool can't.
+    fn stdin_command(&self, _plugin_settings: &PluginSetting, _uri: &Url) -> Option<Vec<String>> {
+        None
+    }
+
+    // Turn the output of the command into diagnostics of the linted file.
+    // Only used by the default run, so plugins replacing it don't need one.
+    fn parse(
+        &self,
+        _plugin_settings: &PluginSetting,
+        _uri: &Url,
+        _stdout: &[u8],
+        _stderr: &[u8],
+    ) -> Result<Vec<Diagnostic>, String> {
+        Err("the output can't be parsed".to_string())
+    }
+
+    // Column the tool counts from, 1 for most tools and 0 for e.g. pylint.
+    fn column_base(&self) -> u32 {
+        1
+    }
+
+    // Project-local binaries, relative to a project directory and in order
+    // of preference. The one nearest to the linted file is used.
+    fn local_binaries(&self) -> &[&str] {
+        &[]
+    }
+
+    // Project scoped plugins analyse the whole project and can report on
+    // files other than the one being linted.
+    fn is_project_scoped(&self) -> bool {
+        false
+    }
+
+    // Plugins which check the whole project whatever file is linted, e.g.
+    // tsc. A workspace lint runs them once instead of for every file.
+    fn checks_whole_project(&self) -> bool {
+        false
+    }
+
+    // Link to the documentation of a rule, shown as the diagnostic
+    // code description.
+    fn rule_url(&self, _rule: &str) -> Option<Url> {
+        None
+    }
+
+    // Tags for a rule, so editors can fade out unused code or strike
+    // through deprecated usages.
+    fn rule_tags(&self, _rule: &str) -> Option<Vec<DiagnosticTag>> {
+        None
+    }
+
+    // Whether the plugin formats documents, only these are asked to.
+    fn can_format(&self) -> bool {
+        false
+    }
+
+    // Format the document text and return the formatted text.
+    // Plugins that can't format keep the default and return None.
+    async fn format(
+        &self,
+        _plugin_settings: PluginSetting,
+        _uri: Url,
+        _text: String,
+        _client: Client,
+    ) -> Option<String> {
+        None
+    }
+}
+
+// Build the command arguments, substituting the {file}, {root} and {config}
+// placeholders. The file is added by append_file unless an argument places it.
+pub fn build_args(plugin_settings: &PluginSetting, file: &str) -> Vec<String> {
+    let mut args = substitute_args(plugin_settings, file);
+
+    if !args_place_file(plugin_settings) {
+        match plugin_settings.append_file {
+            AppendMode::Last => args.push(file.to_string()),
+            AppendMode::First => args.insert(0, file.to_string()),
+        }
+    }
+
+    args
+}
+
+// Arguments passing the config_path to a tool with its own flag, e.g.
+// "--standard=" for PHPCS. Left out when the user already places {config}.
+pub fn config_args(user_settings: &PluginSetting, flag: &str) -> Vec<String> {
+    let placed = user_settings
+        .args
+        .iter()
+        .any(|arg| arg.contains("{config}"));
+    if user_settings.config_path.is_empty() || placed {
+        return vec![];
+    }
+
+    vec![format!("{}{{config}}", flag)]
+}
+
+// Substitute the placeholders without appending the file, for commands
+// which check a whole project rather than a single file.
+pub fn substitute_args(plugin_settings: &PluginSetting, file: &str) -> Vec<String> {
+    plugin_settings
+        .args
+        .iter()
+        .filter(|arg| !drops_arg(plugin_settings, arg))
+        .map(|arg| {
+            arg.replace("{file}", file)
+                .replace("{root}", &plugin_settings.root)
+                .replace("{config}", &plugin_settings.config_path)
+        })
+        .collect()
+}
+
+// Substitute the placeholders for a tool reading the source from stdin. A
+// placed {file} is left out, the tool would read the file on disk as well.
+pub fn stdin_args(plugin_settings: &PluginSetting, file: &str) -> Vec<String> {
+    substitute_args(plugin_settings, file)
+        .into_iter()
+        .filter(|arg| arg != file)
+        .collect()
+}
+
+fn args_place_file(plugin_settings: &PluginSetting) -> bool {
+    plugin_settings
+        .args
+        .iter()
+        .any(|arg| arg.contains("{file}") && !drops_arg(plugin_settings, arg))
+}
+
+// Drop arguments for a config which isn't set.
+fn drops_arg(plugin_settings: &PluginSetting, arg: &str) -> bool {
+    arg.contains("{config}") && plugin_settings.config_path.is_empty()
+}
+
+// Look up an executable on the PATH and return its full path.
+pub fn find_executable(name: &str) -> Option<String> {
+    let paths = env::var_os("PATH")?;
+
+    env::split_paths(&paths)
+        .map(|dir| dir.join(name))
+        .find(|candidate| candidate.is_file())
+        .map(|candidate| candidate.to_string_lossy().to_string())
+}
+
+// Find a tool installed in the project, or else globally. The global one is
+// only looked up on the PATH, spawning the binary to probe it may block on
+// stdin.
+pub fn find_binary(project_root: &str, candidates: &[&str], name: &str) -> Option<String> {
+    find_project_binary(project_root, candidates).or_else(|| find_executable(name))
+}
+
+// Return the first existing project-local binary, in order of preference.
+pub fn find_project_binary(project_root: &str, candidates: &[&str]) -> Option<String> {
+    candidates
+        .iter()
+        .map(|candidate| format!("{}/{}", project_root, candidate))
+        .find(|candidate| Path::new(candidate).is_file())
+}
+
+// Uri of a path from a tool report, relative paths are relative to the
+// project root.
+pub fn report_uri(path: &str, project_root: &str) -> Option<Url> {
+    Url::from_file_path(Path::new(project_root).join(path)).ok()
+}
+
+// Whether a path from a tool report is the file of the uri. Both sides are
+// canonicalized, so symlinks and `..` segments don't cause a mismatch.
+pub fn is_report_file(path: &str, project_root: &str, uri: &Url) -> bool {
+    let report_path = Path::new(project_root).join(path);
+    let file_path = match uri.to_file_path() {
+        Ok(file_path) => file_path,
+        Err(_) => return false,
+    };
+
+    let report_path = report_path.canonicalize().unwrap_or(report_path);
+    let file_path = file_path.canonicalize().unwrap_or(file_path);
+    report_path == file_path
+}
+
+// Run a command and collect its output. The process is killed when the
+// returned future is dropped, e.g. when a newer lint supersedes it. The env
+// is added to the inherited environment. Only the first max_output_bytes of
+// stdout and stderr are kept, so a runaway tool can't exhaust the memory.
+pub async fn execute(
+    cmd: &str,
+    args: Vec<String>,
+    env: &HashMap<String, String>,
+    working_dir: &str,
+    max_output_bytes: usize,
+) -> std::io::Result<Output> {
+    let mut command = tokio::process::Command::new(cmd);
+    command
+        .args(args)
+        .envs(env)
+        .kill_on_drop(true)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if !working_dir.is_empty() {
+        command.current_dir(working_dir);
+    }
+    let mut child = command.spawn()?;
+
+    let stdout = read_limited(cmd, child.stdout.take(), max_output_bytes);
+    let stderr = read_limited(cmd, child.stderr.take(), max_output_bytes);
+    let (stdout, stderr) = tokio::try_join!(stdout, stderr)?;
+    let status = child.wait().await?;
+
+    Ok(Output {
+        status,
+        stdout,
+        stderr,
+    })
+}
+
+// Read up to the limit. The rest is still read, so the process doesn't block
+// on a full pipe, but dropped.
+async fn read_limited(
+    cmd: &str,
+    reader: Option<impl AsyncRead + Unpin>,
+    limit: usize,
+) -> std::io::Result<Vec<u8>> {
+    let mut reader = match reader {
+        Some(reader) => reader,
+        None => return Ok(vec![]),
+    };
+
+    let mut output = vec![];
+    (&mut reader)
+        .take(limit as u64)
+        .read_to_end(&mut output)
+        .await?;
+
+    let dropped = tokio::io::copy(&mut reader, &mut tokio::io::sink()).await?;
+    if dropped > 0 {
+        error!(
+            "{} printed more than {} bytes, the output is truncated",
+            cmd, limit
+        );
+    }
+
+    Ok(output)
+}
+
+// Run the plugin command, once more when `retry_on_empty` is set and the tool
+// printed nothing at all. A clean file still produces a report (e.g. `[]`),
+// so those aren't retried.
+pub async fn execute_plugin(
+    plugin_settings: &PluginSetting,
+    args: Vec<String>,
+) -> std::io::Result<Output> {
+    let output = execute(
+        &plugin_settings.cmd,
+        args.clone(),
+        &plugin_settings.env,
+        &plugin_settings.working_dir,
+        plugin_settings.max_output_bytes,
+    )
+    .await?;
+
+    if !plugin_settings.retry_on_empty || !is_empty_output(&output) {
+        return Ok(output);
+    }
+
+    info!("{} printed nothing, retrying once", plugin_settings.cmd);
+    execute(
+        &plugin_settings.cmd,
+        args,
+        &plugin_settings.env,
+        &plugin_settings.working_dir,
+        plugin_settings.max_output_bytes,
+    )
+    .await
+}
+
+fn is_empty_output(output: &Output) -> bool {
+    output.stdout.iter().all(u8::is_ascii_whitespace)
+        && output.stderr.iter().all(u8::is_ascii_whitespace)
+}
+
+// Convert a 1-based line or column from a tool report to the 0-based LSP
+// position. A malformed 0 stays at 0 instead of underflowing.
+pub fn to_zero_based(number: u32) -> u32 {
+    number.saturating_sub(1)
+}
+
+// Convert a column from a tool report to the 0-based LSP character, for a
+// tool counting from column_base. A malformed column stays at 0.
+pub fn to_character(column: u32, column_base: u32) -> u32 {
+    column.saturating_sub(column_base)
+}
+
+// Convert a byte offset into the text to the LSP position, the character
+// counted in UTF-16 code units. An offset past the end is the end, one inside
+// a character is that character.
+pub fn offset_to_position(text: &str, offset: usize) -> Position {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map(|index| index + 1).unwrap_or(0);
+    Position {
+        line: before.matches('\n').count() as u32,
+        character: before[line_start..].encode_utf16().count() as u32,
+    }
+}
+
+// Parse newline delimited JSON, one value per line. Blank lines are skipped
+// and lines which can't be parsed are logged and left out.
+pub fn parse_ndjson<T: DeserializeOwned>(stdout: &[u8]) -> Vec<T> {
+    String::from_utf8_lossy(stdout)
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                error!("Can't parse output line {}: {}", line, e);
+                None
+            }
+        })
+        .collect()
+}
+
+// Apply the plugin's severity_map to a tool's native severity, the plugin's
+// own mapping is used when the severity isn't remapped.
+pub fn map_severity(
+    plugin_settings: &PluginSetting,
+    native: &str,
+    severity: DiagnosticSeverity,
+) -> DiagnosticSeverity {
+    plugin_settings
+        .severity_map
+        .get(&native.to_lowercase())
+        .and_then(|mapped| parse_severity(mapped))
+        .unwrap_or(severity)
+}
+
+pub fn parse_severity(name: &str) -> Option<DiagnosticSeverity> {
+    match &name.to_lowercase()[..] {
+        "error" => Some(DiagnosticSeverity::ERROR),
+        "warning" => Some(DiagnosticSeverity::WARNING),
+        "info" | "information" => Some(DiagnosticSeverity::INFORMATION),
+        "hint" => Some(DiagnosticSeverity::HINT),
+        _ => None,
+    }
+}
+
+// Tell the user a plugin command couldn't run, e.g. the binary was removed
+// after startup, instead of taking the server down.
+pub async fn log_execute_error(client: &Client, cmd: &str, e: std::io::Error) {
+    let message = match e.kind() {
+        ErrorKind::NotFound => format!("Command {} not found, is it still installed?", cmd),
+        _ => format!("Command {} cant be executed: {}", cmd, e),
+    };
+
+    error!("{}", message);
+    client.log_message(MessageType::ERROR, message).await;
+}
+
+// Run a command with the input written to its stdin and collect the output.
+// Without a working_dir the command runs in the server's. The output is
+// limited like the one of execute.
+pub async fn run_with_stdin(
+    cmd: &str,
+    args: Vec<String>,
+    env: &HashMap<String, String>,
+    working_dir: &str,
+    input: &str,
+    max_output_bytes: usize,
+) -> std::io::Result<Output> {
+    let mut command = tokio::process::Command::new(cmd);
+    command
+        .args(args)
+        .envs(env)
+        .kill_on_drop(true)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if !working_dir.is_empty() {
+        command.current_dir(working_dir);
+    }
+    let mut child = command.spawn()?;
+
+    // Write in the background, so a large output can't block a large input.
+    if let Some(mut stdin) = child.stdin.take() {
+        let input = input.to_string();
+        tokio::spawn(async move {
+            let _ = stdin.write_all(input.as_bytes()).await;
+        });
+    }
+
+    let stdout = read_limited(cmd, child.stdout.take(), max_output_bytes);
+    let stderr = read_limited(cmd, child.stderr.take(), max_output_bytes);
+    let (stdout, stderr) = tokio::try_join!(stdout, stderr)?;
+    let status = child.wait().await?;
+
+    Ok(Output {
+        status,
+        stdout,
+        stderr,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{temp_dir, test_client};
+
+    #[tokio::test]
+    async fn runs_with_stdin_in_the_working_dir() {
+        let dir = temp_dir("stdin-working-dir");
+        let args = vec!["-c".to_string(), "pwd; cat".to_string()];
+
+        let output = run_with_stdin(
+            "sh",
+            args,
+            &HashMap::new(),
+            &dir.to_string_lossy(),
+            "input",
+            DEFAULT_MAX_OUTPUT_BYTES,
+        )
+        .await
+        .unwrap();
+
+        let expected = format!("{}\ninput", dir.canonicalize().unwrap().display());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+    }
+
+    #[test]
+    fn converts_lines_to_zero_based() {
+        assert_eq!(to_zero_based(5), 4);
+        assert_eq!(to_zero_based(1), 0);
+        // E.g. a file level message without a line.
+        assert_eq!(to_zero_based(0), 0);
+    }
+
+    #[test]
+    fn parses_ndjson_skipping_bad_lines() {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Message {
+            line: u32,
+        }
+        let stdout = b"{\"line\": 1}\n\n   \nCompiling foo v0.1.0\n{\"line\": 2}\r\n{\"line\":";
+
+        let messages: Vec<Message> = parse_ndjson(stdout);
+
+        assert_eq!(messages, vec![Message { line: 1 }, Message { line: 2 }]);
+    }
+
+    #[test]
+    fn converts_columns_to_characters() {
+        assert_eq!(to_character(10, 1), 9);
+        assert_eq!(to_character(10, 0), 10);
+        // A malformed column of a 1-based tool.
+        assert_eq!(to_character(0, 1), 0);
+    }
+
+    #[tokio::test]
+    async fn limits_the_stdin_output() {
+        let args = vec![
+            "-c".to_string(),
+            "cat; cat >&2 <<EOF\nfailed\nEOF".to_string(),
+        ];
+
+        let output = run_with_stdin("sh", args, &HashMap::new(), "", &"x".repeat(100), 10)
+            .await
+            .unwrap();
+
+        assert_eq!(output.stdout, b"xxxxxxxxxx");
+        assert_eq!(output.stderr, b"failed\n");
+    }
+
+    fn settings(args: &[&str], config_path: &str) -> PluginSetting {
+        PluginSetting {
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            config_path: config_path.to_string(),
+            root: "/project".to_string(),
+            ..PluginSetting::default()
+        }
+    }
+
+    #[test]
+    fn substitutes_the_placeholders() {
+        let settings = settings(&["-c", "{config}", "--root={root}", "{file}"], "lint.yml");
+
+        assert_eq!(
+            build_args(&settings, "/project/a.php"),
+            vec!["-c", "lint.yml", "--root=/project", "/project/a.php"]
+        );
+    }
+
+    #[test]
+    fn places_the_file_before_other_args() {
+        let settings = settings(&["{file}", "--config={config}"], "lint.yml");
+
+        assert_eq!(
+            build_args(&settings, "/project/a.php"),
+            vec!["/project/a.php", "--config=lint.yml"]
+        );
+    }
+
+    #[test]
+    fn appends_the_file_without_a_file_placeholder() {
+        let settings = settings(&["--format=json", "--config={config}"], "lint.yml");
+
+        assert_eq!(
+            build_args(&settings, "/project/a.php"),
+            vec!["--format=json", "--config=lint.yml", "/project/a.php"]
+        );
+    }
+
+    #[test]
+    fn puts_the_file_first_by_the_append_mode() {
+        let mut settings = settings(&["--", "-std=c++17"], "");
+        settings.append_file = AppendMode::First;
+
+        assert_eq!(
+            build_args(&settings, "/project/a.cpp"),
+            vec!["/project/a.cpp", "--", "-std=c++17"]
+        );
+
+        // A placed file stays where it's placed.
+        settings.args = vec!["-p".to_string(), "{file}".to_string()];
+        assert_eq!(
+            build_args(&settings, "/project/a.cpp"),
+            vec!["-p", "/project/a.cpp"]
+        );
+    }
+
+    #[test]
+    fn drops_config_args_without_a_config() {
+        let settings = settings(&["--config={config}", "{file}", "--strict"], "");
+
+        assert_eq!(
+            build_args(&settings, "/project/a.php"),
+            vec!["/project/a.php", "--strict"]
+        );
+    }
+
+    #[test]
+    fn stdin_args_leave_out_the_file() {
+        let settings = settings(
+            &["--config={config}", "{file}", "--root={root}"],
+            "lint.yml",
+        );
+
+        assert_eq!(
+            stdin_args(&settings, "/project/a.php"),
+            vec!["--config=lint.yml", "--root=/project"]
+        );
+    }
+
+    // A tool printing nothing on its first run, e.g. a daemon still starting.
+    fn flaky_tool(name: &str, retry_on_empty: bool) -> PluginSetting {
+        PluginSetting {
+            cmd: "sh".to_string(),
+            working_dir: temp_dir(name).to_string_lossy().to_string(),
+            retry_on_empty,
+            ..PluginSetting::default()
+        }
+    }
+
+    fn flaky_args() -> Vec<String> {
+        let script = "if [ -e started ]; then echo '[]'; else touch started; fi";
+        vec!["-c".to_string(), script.to_string()]
+    }
+
+    #[tokio::test]
+    async fn retries_empty_output_once() {
+        let settings = flaky_tool("retry-on-empty", true);
+
+        let output = execute_plugin(&settings, flaky_args()).await.unwrap();
+
+        assert_eq!(output.stdout, b"[]\n");
+    }
+
+    #[tokio::test]
+    async fn empty_output_is_kept_without_retry() {
+        let settings = flaky_tool("no-retry-on-empty", false);
+
+        let output = execute_plugin(&settings, flaky_args()).await.unwrap();
+
+        assert!(output.stdout.is_empty());
+    }
+
+    #[tokio::test]
+    async fn missing_command_fails_the_run_only() {
+        let settings = PluginSetting {
+            cmd: "/nonexistent/checkmate-missing-tool".to_string(),
+            ..PluginSetting::default()
+        };
+
+        let error = execute_plugin(&settings, vec![]).await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+
+        let uri = Url::from_file_path("/project/Dockerfile").unwrap();
+        let output = hadolint::HadolintPlugin
+            .run(settings, uri, test_client())
+            .await;
+        assert!(output.is_none());
+    }
 }
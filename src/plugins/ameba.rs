@@ -0,0 +1,204 @@
+use std::{format, vec};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use log::{error, info};
+use tower_lsp::lsp_types::{Diagnostic, NumberOrString, Position, Range};
+use tower_lsp::lsp_types::{DiagnosticSeverity, Url};
+
+use crate::plugins::{
+    find_executable, find_project_binary, map_severity, to_character, to_zero_based, Plugin,
+    PluginSetting,
+};
+use serde_derive::Deserialize;
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+struct AmebaReport {
+    pub sources: Vec<SourceReport>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+struct SourceReport {
+    pub path: String,
+    pub issues: Vec<Issue>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+struct Issue {
+    pub rule_name: String,
+    pub severity: String,
+    pub message: String,
+    pub location: Location,
+    pub end_location: Option<Location>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+struct Location {
+    pub line: u32,
+    pub column: u32,
+}
+
+#[derive(Default)]
+pub struct AmebaPlugin;
+
+#[async_trait]
+impl Plugin for AmebaPlugin {
+    fn get_plugin_id(&self) -> &str {
+        "ameba"
+    }
+
+    fn local_binaries(&self) -> &[&str] {
+        &["bin/ameba"]
+    }
+
+    fn is_installed(
+        &self,
+        settings: DashMap<String, String>,
+        _user_settings: &PluginSetting,
+    ) -> Option<PluginSetting> {
+        let project_root = settings
+            .get("root_uri")
+            .expect("Cant fetch root uri")
+            .to_string()
+            .replace("file://", "");
+
+        let default_args = vec!["--format".to_string(), "json".to_string()];
+        let default_filetypes = vec!["cr".to_string()];
+
+        // Shards installs ameba as a project binary.
+        let ameba =
+            find_project_binary(&project_root, &["bin/ameba"]).or_else(|| find_executable("ameba"));
+        if let Some(ameba) = ameba {
+            info!("Plugin Ameba found");
+            return Some(PluginSetting {
+                cmd: ameba,
+                args: default_args,
+                filetypes: default_filetypes,
+                ..PluginSetting::default()
+            });
+        }
+
+        error!("Ameba cant be executed.");
+        None
+    }
+
+    fn rule_url(&self, rule: &str) -> Option<Url> {
+        Url::parse(&format!(
+            "https://crystal-ameba.github.io/ameba/Ameba/Rule/{}.html",
+            rule
+        ))
+        .ok()
+    }
+
+    fn parse(
+        &self,
+        plugin_settings: &PluginSetting,
+        _uri: &Url,
+        stdout: &[u8],
+        stderr: &[u8],
+    ) -> Result<Vec<Diagnostic>, String> {
+        let report: AmebaReport = match serde_json::from_slice(stdout) {
+            Ok(report) => report,
+            Err(_) if !stderr.is_empty() => {
+                return Err(String::from_utf8_lossy(stderr).to_string());
+            }
+            Err(e) => return Err(format!("can't parse the report: {}", e)),
+        };
+
+        Ok(report_diagnostics(
+            &report,
+            plugin_settings,
+            self.column_base(),
+        ))
+    }
+}
+
+// Turn the report into diagnostics, without running anything.
+fn report_diagnostics(
+    report: &AmebaReport,
+    plugin_settings: &PluginSetting,
+    column_base: u32,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    for source_report in &report.sources {
+        for issue in &source_report.issues {
+            let severity = match &issue.severity[..] {
+                "Error" => DiagnosticSeverity::ERROR,
+                "Warning" => DiagnosticSeverity::WARNING,
+                "Convention" => DiagnosticSeverity::HINT,
+                _ => DiagnosticSeverity::INFORMATION,
+            };
+            let severity = map_severity(plugin_settings, &issue.severity, severity);
+
+            let end_location = issue.end_location.as_ref().unwrap_or(&issue.location);
+            let item = Diagnostic::new(
+                Range::new(
+                    Position {
+                        line: to_zero_based(issue.location.line),
+                        character: to_character(issue.location.column, column_base),
+                    },
+                    Position {
+                        line: to_zero_based(end_location.line),
+                        // The end column is inclusive.
+                        character: end_location.column,
+                    },
+                ),
+                Some(severity),
+                Some(NumberOrString::String(issue.rule_name.clone())),
+                None,
+                issue.message.clone(),
+                None,
+                None,
+            );
+
+            diagnostics.push(item);
+        }
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fixture;
+
+    fn parse(stdout: &[u8], stderr: &[u8]) -> Result<Vec<Diagnostic>, String> {
+        AmebaPlugin.parse(
+            &PluginSetting::default(),
+            &Url::from_file_path("/project/src/app.cr").unwrap(),
+            stdout,
+            stderr,
+        )
+    }
+
+    #[test]
+    fn parses_the_report() {
+        let diagnostics = parse(&fixture("ameba/report.json"), b"").unwrap();
+
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(2, 4), Position::new(2, 5))
+        );
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(
+            diagnostics[0].code,
+            Some(NumberOrString::String("Lint/UselessAssign".to_string()))
+        );
+        assert_eq!(
+            diagnostics[1].range,
+            Range::new(Position::new(6, 2), Position::new(6, 14))
+        );
+        assert_eq!(diagnostics[1].severity, Some(DiagnosticSeverity::HINT));
+    }
+
+    #[test]
+    fn malformed_output_is_an_error() {
+        assert_eq!(
+            parse(b"", b"Error: unable to parse .ameba.yml"),
+            Err("Error: unable to parse .ameba.yml".to_string())
+        );
+        assert!(parse(b"", b"").is_err());
+    }
+}
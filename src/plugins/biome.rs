@@ -0,0 +1,176 @@
+use std::{format, fs, vec};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use log::{error, info};
+use serde_json::Value;
+use tower_lsp::lsp_types::{Diagnostic, NumberOrString, Range};
+use tower_lsp::lsp_types::{DiagnosticSeverity, Url};
+
+use crate::plugins::{
+    find_executable, find_project_binary, is_report_file, map_severity, offset_to_position, Plugin,
+    PluginSetting,
+};
+use serde_derive::Deserialize;
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+struct BiomeReport {
+    #[serde(default)]
+    pub diagnostics: Vec<BiomeDiagnostic>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+struct BiomeDiagnostic {
+    #[serde(default)]
+    pub category: String,
+    pub severity: String,
+    pub description: String,
+    pub location: Location,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+struct Location {
+    // `{"file": "src/index.js"}`, or a plain string in older versions.
+    pub path: Value,
+    // Byte offsets into the file, missing for e.g. config errors.
+    pub span: Option<[usize; 2]>,
+}
+
+#[derive(Default)]
+pub struct BiomePlugin;
+
+#[async_trait]
+impl Plugin for BiomePlugin {
+    fn get_plugin_id(&self) -> &str {
+        "biome"
+    }
+
+    fn local_binaries(&self) -> &[&str] {
+        &["node_modules/.bin/biome"]
+    }
+
+    fn is_installed(
+        &self,
+        settings: DashMap<String, String>,
+        _user_settings: &PluginSetting,
+    ) -> Option<PluginSetting> {
+        let project_root = settings
+            .get("root_uri")
+            .expect("Cant fetch root uri")
+            .to_string()
+            .replace("file://", "");
+
+        let default_args = vec!["lint".to_string(), "--reporter=json".to_string()];
+        let default_filetypes = vec![
+            "js".to_string(),
+            "ts".to_string(),
+            "jsx".to_string(),
+            "tsx".to_string(),
+            "json".to_string(),
+        ];
+
+        // Biome looks up biome.json from the working directory.
+        let biome = find_project_binary(&project_root, self.local_binaries())
+            .or_else(|| find_executable("biome"));
+        if let Some(biome) = biome {
+            info!("Plugin Biome found, using {}", biome);
+            return Some(PluginSetting {
+                cmd: biome,
+                args: default_args,
+                filetypes: default_filetypes,
+                working_dir: project_root,
+                ..PluginSetting::default()
+            });
+        }
+
+        error!("Biome cant be executed.");
+        None
+    }
+
+    fn rule_url(&self, rule: &str) -> Option<Url> {
+        // E.g. "lint/suspicious/noDebugger" is documented at "no-debugger".
+        let name = rule.strip_prefix("lint/")?.rsplit('/').next()?;
+        let mut page = String::new();
+        for character in name.chars() {
+            if character.is_ascii_uppercase() {
+                page.push('-');
+            }
+            page.push(character.to_ascii_lowercase());
+        }
+
+        Url::parse(&format!("https://biomejs.dev/linter/rules/{}", page)).ok()
+    }
+
+    fn parse(
+        &self,
+        plugin_settings: &PluginSetting,
+        uri: &Url,
+        stdout: &[u8],
+        stderr: &[u8],
+    ) -> Result<Vec<Diagnostic>, String> {
+        // Biome warns on stderr that the JSON reporter is unstable, so stderr
+        // only counts when the report can't be parsed.
+        let report: BiomeReport = match serde_json::from_slice(stdout) {
+            Ok(report) => report,
+            Err(_) if !stderr.is_empty() => {
+                return Err(String::from_utf8_lossy(stderr).to_string());
+            }
+            Err(_) => BiomeReport::default(),
+        };
+
+        // The spans are byte offsets, the file on disk is what was linted.
+        let text = uri
+            .to_file_path()
+            .ok()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .unwrap_or_default();
+
+        Ok(report_diagnostics(&report, plugin_settings, uri, &text))
+    }
+}
+
+// Turn the report into diagnostics of the linted file, without running
+// anything.
+fn report_diagnostics(
+    report: &BiomeReport,
+    plugin_settings: &PluginSetting,
+    uri: &Url,
+    text: &str,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    for message in &report.diagnostics {
+        let path = match &message.location.path {
+            Value::String(path) => path.as_str(),
+            path => path.get("file").and_then(Value::as_str).unwrap_or_default(),
+        };
+        if !is_report_file(path, &plugin_settings.root, uri) {
+            continue;
+        }
+
+        let severity = match &message.severity[..] {
+            "fatal" | "error" => DiagnosticSeverity::ERROR,
+            "warning" => DiagnosticSeverity::WARNING,
+            "information" => DiagnosticSeverity::INFORMATION,
+            _ => DiagnosticSeverity::HINT,
+        };
+        let severity = map_severity(plugin_settings, &message.severity, severity);
+
+        let [start, end] = message.location.span.unwrap_or([0, 0]);
+        let item = Diagnostic::new(
+            Range::new(
+                offset_to_position(text, start),
+                offset_to_position(text, end),
+            ),
+            Some(severity),
+            Some(NumberOrString::String(message.category.clone())),
+            None,
+            message.description.clone(),
+            None,
+            None,
+        );
+
+        diagnostics.push(item);
+    }
+
+    diagnostics
+}
@@ -0,0 +1,178 @@
+use std::{format, str, vec};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use log::{error, info};
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, MessageType, Position, Range, Url};
+use tower_lsp::Client;
+
+use crate::plugins::{
+    build_args, execute_plugin, find_executable, find_project_binary, log_execute_error,
+    map_severity, run_with_stdin, stdin_args, Plugin, PluginOutput, PluginSetting,
+};
+
+#[derive(Default)]
+pub struct BlackPlugin;
+
+#[async_trait]
+impl Plugin for BlackPlugin {
+    fn get_plugin_id(&self) -> &str {
+        "black"
+    }
+
+    fn local_binaries(&self) -> &[&str] {
+        &[".venv/bin/black"]
+    }
+
+    fn is_installed(
+        &self,
+        settings: DashMap<String, String>,
+        _user_settings: &PluginSetting,
+    ) -> Option<PluginSetting> {
+        let project_root = settings
+            .get("root_uri")
+            .expect("Cant fetch root uri")
+            .to_string()
+            .replace("file://", "");
+
+        let default_args = vec!["--check".to_string(), "--quiet".to_string()];
+        let default_filetypes = vec!["py".to_string()];
+
+        let black = find_project_binary(&project_root, &[".venv/bin/black"])
+            .or_else(|| find_executable("black"));
+        if let Some(black) = black {
+            info!("Plugin Black found");
+            return Some(PluginSetting {
+                cmd: black,
+                args: default_args,
+                filetypes: default_filetypes,
+                ..PluginSetting::default()
+            });
+        }
+
+        error!("Black cant be executed.");
+        None
+    }
+
+    async fn run(
+        &self,
+        plugin_settings: PluginSetting,
+        uri: Url,
+        client: Client,
+    ) -> Option<PluginOutput> {
+        let file = uri.to_string().replace("file://", "");
+        let args = build_args(&plugin_settings, &file);
+
+        client
+            .log_message(
+                MessageType::LOG,
+                format!("Running Black with command {}", plugin_settings.cmd),
+            )
+            .await;
+
+        let output = match execute_plugin(&plugin_settings, args).await {
+            Ok(output) => output,
+            Err(e) => {
+                log_execute_error(&client, &plugin_settings.cmd, e).await;
+                return None;
+            }
+        };
+
+        // --check exits with 1 when the file would be reformatted and 123 when
+        // Black fails.
+        let mut plugin_output = PluginOutput::default();
+        let diagnostics = plugin_output.diagnostics.entry(uri.clone()).or_default();
+        match output.status.code() {
+            Some(0) => {}
+            Some(1) => diagnostics.push(Diagnostic::new(
+                Range::new(
+                    Position {
+                        line: 0,
+                        character: 0,
+                    },
+                    Position {
+                        line: 0,
+                        character: 0,
+                    },
+                ),
+                Some(map_severity(
+                    &plugin_settings,
+                    "info",
+                    DiagnosticSeverity::INFORMATION,
+                )),
+                None,
+                None,
+                "File would be reformatted by Black".to_string(),
+                None,
+                None,
+            )),
+            _ => {
+                error!(
+                    "Black returned error: {}",
+                    str::from_utf8(&output.stderr).unwrap_or_default()
+                );
+                return None;
+            }
+        }
+
+        client
+            .log_message(MessageType::LOG, "Black ended".to_string())
+            .await;
+        Some(plugin_output)
+    }
+
+    fn can_format(&self) -> bool {
+        true
+    }
+
+    async fn format(
+        &self,
+        plugin_settings: PluginSetting,
+        uri: Url,
+        text: String,
+        client: Client,
+    ) -> Option<String> {
+        // The source is read from stdin, the path picks the project config.
+        let file = uri.to_string().replace("file://", "");
+        let mut args: Vec<String> = stdin_args(&plugin_settings, &file)
+            .into_iter()
+            .filter(|arg| arg != "--check")
+            .collect();
+        args.push(format!("--stdin-filename={}", file));
+        args.push("-".to_string());
+
+        client
+            .log_message(
+                MessageType::LOG,
+                format!("Running Black format with command {}", plugin_settings.cmd),
+            )
+            .await;
+
+        let output = match run_with_stdin(
+            &plugin_settings.cmd,
+            args,
+            &plugin_settings.env,
+            &plugin_settings.working_dir,
+            &text,
+            plugin_settings.max_output_bytes,
+        )
+        .await
+        {
+            Ok(output) => output,
+            Err(e) => {
+                error!("Black cant be executed: {}", e);
+                return None;
+            }
+        };
+
+        if !output.status.success() {
+            error!(
+                "Black returned error: {}",
+                str::from_utf8(&output.stderr).unwrap_or_default()
+            );
+            return None;
+        }
+
+        String::from_utf8(output.stdout).ok()
+    }
+}
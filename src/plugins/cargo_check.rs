@@ -0,0 +1,122 @@
+use std::{format, path::Path, str, vec};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use log::{error, info};
+use tower_lsp::lsp_types::{MessageType, Url};
+use tower_lsp::Client;
+
+use crate::plugins::cargo_common::parse_cargo_messages;
+use crate::plugins::{
+    execute_plugin, find_executable, log_execute_error, substitute_args, Plugin, PluginOutput,
+    PluginSetting,
+};
+
+#[derive(Default)]
+pub struct CargoCheckPlugin;
+
+#[async_trait]
+impl Plugin for CargoCheckPlugin {
+    fn get_plugin_id(&self) -> &str {
+        "cargo-check"
+    }
+
+    fn is_installed(
+        &self,
+        settings: DashMap<String, String>,
+        _user_settings: &PluginSetting,
+    ) -> Option<PluginSetting> {
+        let project_root = settings
+            .get("root_uri")
+            .expect("Cant fetch root uri")
+            .to_string()
+            .replace("file://", "");
+
+        if !Path::new(&format!("{}/Cargo.toml", project_root)).is_file() {
+            error!("Cargo.toml not found, cargo check is disabled.");
+            return None;
+        }
+
+        // Cargo checks the whole workspace, the saved file isn't passed.
+        let default_args = vec![
+            "check".to_string(),
+            "--message-format=json".to_string(),
+            "--manifest-path={root}/Cargo.toml".to_string(),
+        ];
+        let default_filetypes = vec!["rs".to_string()];
+
+        if let Some(cargo) = find_executable("cargo") {
+            info!("Plugin Cargo check found");
+            return Some(PluginSetting {
+                cmd: cargo,
+                args: default_args,
+                filetypes: default_filetypes,
+                ..PluginSetting::default()
+            });
+        }
+
+        error!("Cargo cant be executed.");
+        None
+    }
+
+    fn is_project_scoped(&self) -> bool {
+        true
+    }
+
+    fn checks_whole_project(&self) -> bool {
+        true
+    }
+
+    fn rule_url(&self, rule: &str) -> Option<Url> {
+        Url::parse(&format!(
+            "https://doc.rust-lang.org/error_codes/{}.html",
+            rule
+        ))
+        .ok()
+    }
+
+    async fn run(
+        &self,
+        plugin_settings: PluginSetting,
+        uri: Url,
+        client: Client,
+    ) -> Option<PluginOutput> {
+        let file = uri.to_string().replace("file://", "");
+        let args = substitute_args(&plugin_settings, &file);
+
+        client
+            .log_message(
+                MessageType::LOG,
+                format!("Running Cargo check with command {}", plugin_settings.cmd),
+            )
+            .await;
+
+        let output = match execute_plugin(&plugin_settings, args).await {
+            Ok(output) => output,
+            Err(e) => {
+                log_execute_error(&client, &plugin_settings.cmd, e).await;
+                return None;
+            }
+        };
+
+        // Compiler messages are reported on stdout, stderr only holds the
+        // build progress.
+        if output.stdout.is_empty() {
+            error!(
+                "Cargo check returned error: {}",
+                str::from_utf8(&output.stderr).unwrap_or_default()
+            );
+            return None;
+        }
+
+        // Every file with problems is published under its own uri.
+        let plugin_output = PluginOutput {
+            diagnostics: parse_cargo_messages(&output.stdout, &plugin_settings, self.column_base()),
+        };
+
+        client
+            .log_message(MessageType::LOG, "Cargo check ended".to_string())
+            .await;
+        Some(plugin_output)
+    }
+}
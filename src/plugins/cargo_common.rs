@@ -0,0 +1,187 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::plugins::{map_severity, parse_ndjson, to_character, to_zero_based, PluginSetting};
+use serde_derive::Deserialize;
+use tower_lsp::lsp_types::{
+    Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, Location, NumberOrString,
+    Position, Range, Url,
+};
+
+// Shared parsing of the JSON messages cargo prints with --message-format=json.
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+struct CargoMessage {
+    pub reason: String,
+    pub message: Option<CompilerMessage>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+struct CompilerMessage {
+    pub message: String,
+    pub code: Option<CompilerCode>,
+    pub level: String,
+    pub spans: Vec<Span>,
+    #[serde(default)]
+    pub children: Vec<CompilerMessage>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+struct CompilerCode {
+    pub code: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+struct Span {
+    pub file_name: String,
+    pub line_start: u32,
+    pub line_end: u32,
+    pub column_start: u32,
+    pub column_end: u32,
+    pub is_primary: bool,
+    pub label: Option<String>,
+}
+
+// Parse cargo's line delimited JSON output into diagnostics per file. Span
+// file names are relative to the workspace root.
+pub fn parse_cargo_messages(
+    stdout: &[u8],
+    plugin_settings: &PluginSetting,
+    column_base: u32,
+) -> HashMap<Url, Vec<Diagnostic>> {
+    let project_root = &plugin_settings.root;
+    let mut diagnostics: HashMap<Url, Vec<Diagnostic>> = HashMap::new();
+
+    for cargo_message in parse_ndjson::<CargoMessage>(stdout) {
+        if cargo_message.reason != "compiler-message" {
+            continue;
+        }
+
+        let message = match cargo_message.message {
+            Some(message) => message,
+            None => continue,
+        };
+
+        // Summaries such as "aborting due to previous error" have no span.
+        let primary_span = match message.spans.iter().find(|span| span.is_primary) {
+            Some(primary_span) => primary_span,
+            None => continue,
+        };
+
+        let uri = match span_uri(primary_span, project_root) {
+            Some(uri) => uri,
+            None => continue,
+        };
+
+        // Secondary spans and notes with a location become related information,
+        // notes without one are appended to the message.
+        let mut text = message.message.clone();
+        let mut related_information = vec![];
+        for span in message.spans.iter().filter(|span| !span.is_primary) {
+            if let Some(location) = span_location(span, project_root, column_base) {
+                related_information.push(DiagnosticRelatedInformation {
+                    location,
+                    message: span.label.clone().unwrap_or_default(),
+                });
+            }
+        }
+
+        for child in &message.children {
+            if child.spans.is_empty() {
+                text.push_str(&format!("\n{}: {}", child.level, child.message));
+                continue;
+            }
+
+            for span in &child.spans {
+                if let Some(location) = span_location(span, project_root, column_base) {
+                    related_information.push(DiagnosticRelatedInformation {
+                        location,
+                        message: format!("{}: {}", child.level, child.message),
+                    });
+                }
+            }
+        }
+
+        let severity = match &message.level[..] {
+            "warning" => DiagnosticSeverity::WARNING,
+            "note" | "help" => DiagnosticSeverity::HINT,
+            _ => DiagnosticSeverity::ERROR,
+        };
+        let severity = map_severity(plugin_settings, &message.level, severity);
+
+        let item = Diagnostic::new(
+            span_range(primary_span, column_base),
+            Some(severity),
+            message.code.map(|code| NumberOrString::String(code.code)),
+            None,
+            text,
+            if related_information.is_empty() {
+                None
+            } else {
+                Some(related_information)
+            },
+            None,
+        );
+
+        diagnostics.entry(uri).or_default().push(item);
+    }
+
+    diagnostics
+}
+
+fn span_uri(span: &Span, project_root: &str) -> Option<Url> {
+    Url::from_file_path(Path::new(project_root).join(&span.file_name)).ok()
+}
+
+fn span_location(span: &Span, project_root: &str, column_base: u32) -> Option<Location> {
+    Some(Location {
+        uri: span_uri(span, project_root)?,
+        range: span_range(span, column_base),
+    })
+}
+
+// Span lines are 1-based.
+fn span_range(span: &Span, column_base: u32) -> Range {
+    Range::new(
+        Position {
+            line: to_zero_based(span.line_start),
+            character: to_character(span.column_start, column_base),
+        },
+        Position {
+            line: to_zero_based(span.line_end),
+            character: to_character(span.column_end, column_base),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fixture;
+
+    #[test]
+    fn attaches_notes_in_other_files() {
+        let settings = PluginSetting {
+            root: "/project".to_string(),
+            ..PluginSetting::default()
+        };
+
+        let diagnostics = parse_cargo_messages(&fixture("cargo/messages.ndjson"), &settings, 1);
+
+        let main = &diagnostics[&Url::from_file_path("/project/src/main.rs").unwrap()];
+        assert_eq!(main.len(), 1);
+        assert_eq!(
+            main[0].range,
+            Range::new(Position::new(6, 17), Position::new(6, 24))
+        );
+        assert_eq!(
+            main[0].related_information,
+            Some(vec![DiagnosticRelatedInformation {
+                location: Location {
+                    uri: Url::from_file_path("/project/src/lib.rs").unwrap(),
+                    range: Range::new(Position::new(2, 7), Position::new(2, 12)),
+                },
+                message: "note: function defined here".to_string(),
+            }])
+        );
+    }
+}
@@ -0,0 +1,260 @@
+use std::{collections::HashMap, format, path::Path, str, vec};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use log::{error, info};
+use regex::Regex;
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticRelatedInformation, NumberOrString};
+use tower_lsp::lsp_types::{DiagnosticSeverity, Location, Position, Range, Url};
+
+use crate::plugins::{
+    build_args, find_executable, is_report_file, map_severity, to_character, to_zero_based, Plugin,
+    PluginSetting,
+};
+
+#[derive(Default)]
+pub struct ClangTidyPlugin;
+
+#[async_trait]
+impl Plugin for ClangTidyPlugin {
+    fn get_plugin_id(&self) -> &str {
+        "clang-tidy"
+    }
+
+    fn is_installed(
+        &self,
+        settings: DashMap<String, String>,
+        user_settings: &PluginSetting,
+    ) -> Option<PluginSetting> {
+        let project_root = settings
+            .get("root_uri")
+            .expect("Cant fetch root uri")
+            .to_string()
+            .replace("file://", "");
+
+        // A compilation database at the root is picked up on its own.
+        let mut default_args = vec![];
+        if user_settings.compilation_database.is_empty()
+            && Path::new(&format!("{}/compile_commands.json", project_root)).is_file()
+        {
+            info!("compile_commands.json found, passing it to Clang-Tidy");
+            default_args.push("-p={root}".to_string());
+        }
+        let default_filetypes = vec![
+            "c".to_string(),
+            "cpp".to_string(),
+            "cc".to_string(),
+            "h".to_string(),
+            "hpp".to_string(),
+        ];
+
+        if let Some(clang_tidy) = find_executable("clang-tidy") {
+            info!("Plugin Clang-Tidy found");
+            return Some(PluginSetting {
+                cmd: clang_tidy,
+                args: default_args,
+                filetypes: default_filetypes,
+                ..PluginSetting::default()
+            });
+        }
+
+        error!("Clang-Tidy cant be executed.");
+        None
+    }
+
+    fn rule_url(&self, rule: &str) -> Option<Url> {
+        // Checks are documented per group, e.g. bugprone-use-after-move is
+        // at bugprone/use-after-move.html. The clang groups have a dash in
+        // their name, and compiler warnings have no page.
+        let (group, check) = if let Some(check) = rule.strip_prefix("clang-analyzer-") {
+            ("clang-analyzer", check)
+        } else if rule.starts_with("clang-diagnostic-") {
+            return None;
+        } else {
+            rule.split_once('-')?
+        };
+        Url::parse(&format!(
+            "https://clang.llvm.org/extra/clang-tidy/checks/{}/{}.html",
+            group, check
+        ))
+        .ok()
+    }
+
+    fn command(&self, plugin_settings: &PluginSetting, uri: &Url) -> Vec<String> {
+        let file = uri.to_string().replace("file://", "");
+        let mut args = build_args(plugin_settings, &file);
+
+        // Without a compilation database the compile flags follow `--`,
+        // which also stops clang-tidy from searching for one.
+        let has_database = args.iter().any(|arg| arg == "-p" || arg.starts_with("-p="));
+        match compilation_database_dir(&plugin_settings.compilation_database) {
+            Some(build_dir) => args.push(format!("-p={}", build_dir)),
+            None if !has_database && !args.iter().any(|arg| arg == "--") => {
+                args.push("--".to_string())
+            }
+            None => {}
+        }
+
+        args
+    }
+
+    fn parse(
+        &self,
+        plugin_settings: &PluginSetting,
+        uri: &Url,
+        stdout: &[u8],
+        _stderr: &[u8],
+    ) -> Result<Vec<Diagnostic>, String> {
+        // Warnings in included headers are left out, they aren't the saved
+        // file's problems and would stick around in files never opened.
+        let output = str::from_utf8(stdout).unwrap_or_default();
+        let diagnostics = parse_output(output, plugin_settings, self.column_base())
+            .into_iter()
+            .filter(|(file_uri, _)| match file_uri.to_file_path() {
+                Ok(path) => is_report_file(&path.to_string_lossy(), &plugin_settings.root, uri),
+                Err(_) => false,
+            })
+            .flat_map(|(_, diagnostics)| diagnostics)
+            .collect();
+
+        Ok(diagnostics)
+    }
+}
+
+// The -p option takes the build directory holding compile_commands.json.
+fn compilation_database_dir(compilation_database: &str) -> Option<String> {
+    if compilation_database.is_empty() {
+        return None;
+    }
+
+    let path = Path::new(compilation_database);
+    if path.ends_with("compile_commands.json") {
+        return path.parent().map(|dir| dir.to_string_lossy().to_string());
+    }
+
+    Some(compilation_database.to_string())
+}
+
+// Parse `file:line:col: severity: message [check-name]` lines. Notes belong
+// to the preceding warning or error and are attached as related information.
+fn parse_output(
+    output: &str,
+    plugin_settings: &PluginSetting,
+    column_base: u32,
+) -> HashMap<Url, Vec<Diagnostic>> {
+    let line_regex =
+        Regex::new(r"^(.+?):(\d+):(\d+): (warning|error|note): (.*?)(?: \[([^\]]+)\])?$").unwrap();
+
+    let mut diagnostics: HashMap<Url, Vec<Diagnostic>> = HashMap::new();
+    let mut last_uri: Option<Url> = None;
+
+    for line in output.lines() {
+        let captures = match line_regex.captures(line) {
+            Some(captures) => captures,
+            None => continue,
+        };
+
+        let uri = match Url::from_file_path(&captures[1]) {
+            Ok(uri) => uri,
+            Err(_) => continue,
+        };
+        let line_number: u32 = captures[2].parse().unwrap_or(1);
+        let column: u32 = captures[3].parse().unwrap_or(1);
+        let position = Position {
+            line: to_zero_based(line_number),
+            character: to_character(column, column_base),
+        };
+        let range = Range::new(position, position);
+        let message = captures[5].to_string();
+
+        if &captures[4] == "note" {
+            let last_diagnostic = last_uri
+                .as_ref()
+                .and_then(|last_uri| diagnostics.get_mut(last_uri))
+                .and_then(|file_diagnostics| file_diagnostics.last_mut());
+            if let Some(last_diagnostic) = last_diagnostic {
+                last_diagnostic
+                    .related_information
+                    .get_or_insert_with(Vec::new)
+                    .push(DiagnosticRelatedInformation {
+                        location: Location { uri, range },
+                        message,
+                    });
+            }
+            continue;
+        }
+
+        let severity = match &captures[4] {
+            "error" => DiagnosticSeverity::ERROR,
+            _ => DiagnosticSeverity::WARNING,
+        };
+        let severity = map_severity(plugin_settings, &captures[4], severity);
+
+        let item = Diagnostic::new(
+            range,
+            Some(severity),
+            captures
+                .get(6)
+                .map(|check| NumberOrString::String(check.as_str().to_string())),
+            None,
+            message,
+            None,
+            None,
+        );
+
+        diagnostics.entry(uri.clone()).or_default().push(item);
+        last_uri = Some(uri);
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fixture;
+
+    fn url(rule: &str) -> Option<String> {
+        ClangTidyPlugin.rule_url(rule).map(|url| url.to_string())
+    }
+
+    #[test]
+    fn links_the_check_documentation() {
+        assert_eq!(
+            url("bugprone-use-after-move").as_deref(),
+            Some("https://clang.llvm.org/extra/clang-tidy/checks/bugprone/use-after-move.html")
+        );
+        assert_eq!(
+            url("clang-analyzer-core.NullDereference").as_deref(),
+            Some("https://clang.llvm.org/extra/clang-tidy/checks/clang-analyzer/core.NullDereference.html")
+        );
+        assert_eq!(url("clang-diagnostic-unused-variable"), None);
+    }
+
+    #[test]
+    fn parses_the_output() {
+        let output = String::from_utf8(fixture("clang_tidy/output.txt")).unwrap();
+
+        let diagnostics = parse_output(&output, &PluginSetting::default(), 1);
+
+        let main = &diagnostics[&Url::from_file_path("/project/src/main.c").unwrap()];
+        assert_eq!(main.len(), 2);
+        assert_eq!(main[0].range.start, Position::new(5, 11));
+        assert_eq!(main[0].severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(
+            main[0].code,
+            Some(NumberOrString::String(
+                "clang-analyzer-core.NullDereference".to_string()
+            ))
+        );
+        assert_eq!(
+            main[0].related_information.as_ref().unwrap()[0].message,
+            "'p' initialized to a null pointer value"
+        );
+        assert_eq!(main[1].severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(
+            main[1].code,
+            Some(NumberOrString::String("clang-diagnostic-error".to_string()))
+        );
+    }
+}
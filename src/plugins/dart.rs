@@ -0,0 +1,162 @@
+use std::{format, path::Path, str, vec};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use log::{error, info};
+use tower_lsp::lsp_types::{Diagnostic, NumberOrString, Position, Range};
+use tower_lsp::lsp_types::{DiagnosticSeverity, Url};
+
+use crate::plugins::{
+    find_executable, is_report_file, map_severity, to_character, to_zero_based, Plugin,
+    PluginSetting,
+};
+
+#[derive(Default)]
+pub struct DartPlugin;
+
+#[async_trait]
+impl Plugin for DartPlugin {
+    fn get_plugin_id(&self) -> &str {
+        "dart"
+    }
+
+    fn is_installed(
+        &self,
+        settings: DashMap<String, String>,
+        _user_settings: &PluginSetting,
+    ) -> Option<PluginSetting> {
+        let project_root = settings
+            .get("root_uri")
+            .expect("Cant fetch root uri")
+            .to_string()
+            .replace("file://", "");
+
+        if !Path::new(&format!("{}/pubspec.yaml", project_root)).is_file() {
+            error!("pubspec.yaml not found, dart analyze is disabled.");
+            return None;
+        }
+
+        let default_args = vec![
+            "analyze".to_string(),
+            "--format".to_string(),
+            "machine".to_string(),
+        ];
+        let default_filetypes = vec!["dart".to_string()];
+
+        if let Some(dart) = find_executable("dart") {
+            info!("Plugin Dart found");
+            return Some(PluginSetting {
+                cmd: dart,
+                args: default_args,
+                filetypes: default_filetypes,
+                ..PluginSetting::default()
+            });
+        }
+
+        error!("Dart cant be executed.");
+        None
+    }
+
+    fn parse(
+        &self,
+        plugin_settings: &PluginSetting,
+        uri: &Url,
+        stdout: &[u8],
+        stderr: &[u8],
+    ) -> Result<Vec<Diagnostic>, String> {
+        // Older SDKs print the machine format to stderr.
+        let output = if stdout.iter().all(u8::is_ascii_whitespace) {
+            stderr
+        } else {
+            stdout
+        };
+
+        Ok(parse_output(
+            str::from_utf8(output).unwrap_or_default(),
+            plugin_settings,
+            uri,
+            self.column_base(),
+        ))
+    }
+}
+
+// Parse `SEVERITY|TYPE|CODE|file|line|col|length|message` lines, anything
+// else, e.g. "Analyzing ...", is skipped.
+fn parse_output(
+    output: &str,
+    plugin_settings: &PluginSetting,
+    uri: &Url,
+    column_base: u32,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+
+    for line in output.lines() {
+        let fields: Vec<&str> = line.splitn(8, '|').collect();
+        if fields.len() != 8 {
+            continue;
+        }
+
+        if !is_report_file(fields[3], &plugin_settings.root, uri) {
+            continue;
+        }
+
+        let severity = match fields[0] {
+            "ERROR" => DiagnosticSeverity::ERROR,
+            "WARNING" => DiagnosticSeverity::WARNING,
+            "INFO" => DiagnosticSeverity::INFORMATION,
+            _ => continue,
+        };
+        let severity = map_severity(plugin_settings, fields[0], severity);
+
+        let line_number: u32 = fields[4].parse().unwrap_or(1);
+        let column: u32 = fields[5].parse().unwrap_or(1);
+        let length: u32 = fields[6].parse().unwrap_or(0);
+        let start = Position {
+            line: to_zero_based(line_number),
+            character: to_character(column, column_base),
+        };
+        let end = Position {
+            line: start.line,
+            character: start.character + length,
+        };
+
+        // Pipes in the message are escaped.
+        let item = Diagnostic::new(
+            Range::new(start, end),
+            Some(severity),
+            Some(NumberOrString::String(fields[2].to_lowercase())),
+            None,
+            fields[7].replace("\\|", "|"),
+            None,
+            None,
+        );
+
+        diagnostics.push(item);
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_columns_by_the_column_base() {
+        let output = "Analyzing project...\nINFO|LINT|prefer_const_constructors|/project/lib/main.dart|12|10|15|Use 'const' with the constructor to improve performance.\n";
+        let uri = Url::from_file_path("/project/lib/main.dart").unwrap();
+        let settings = PluginSetting {
+            root: "/project".to_string(),
+            ..PluginSetting::default()
+        };
+
+        let diagnostics = parse_output(output, &settings, &uri, DartPlugin.column_base());
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(11, 9), Position::new(11, 24))
+        );
+
+        let diagnostics = parse_output(output, &settings, &uri, 0);
+        assert_eq!(diagnostics[0].range.start, Position::new(11, 10));
+    }
+}
@@ -1,14 +1,17 @@
-use std::{format, fs::metadata, process::Command, str, vec};
+use std::{format, path::Path, str, vec};
 
 use async_trait::async_trait;
 use dashmap::DashMap;
 use log::{error, info};
 use serde_json::Value;
-use tower_lsp::lsp_types::{Diagnostic, Position, Range};
-use tower_lsp::lsp_types::{DiagnosticSeverity, MessageType, Url};
+use tower_lsp::lsp_types::{Diagnostic, NumberOrString, Position, Range};
+use tower_lsp::lsp_types::{DiagnosticSeverity, DiagnosticTag, MessageType, Url};
 use tower_lsp::Client;
 
-use crate::plugins::{Plugin, PluginOutput, PluginSetting};
+use crate::plugins::{
+    config_args, find_executable, find_project_binary, map_severity, run_with_stdin, stdin_args,
+    to_character, to_zero_based, Plugin, PluginSetting,
+};
 use serde_derive::Deserialize;
 
 pub type EslintReport = Vec<FileReport>;
@@ -18,25 +21,43 @@ pub type EslintReport = Vec<FileReport>;
 pub(crate) struct FileReport {
     pub file_path: String,
     pub messages: Vec<FileMessage>,
+    #[serde(default)]
     pub suppressed_messages: Vec<Value>,
     pub error_count: i64,
     pub fatal_error_count: i64,
     pub warning_count: i64,
     pub fixable_error_count: i64,
     pub fixable_warning_count: i64,
+    #[serde(default)]
     pub source: String,
+    #[serde(default)]
+    pub output: Option<String>,
+    #[serde(default)]
     pub used_deprecated_rules: Vec<Value>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct FileMessage {
+    // Messages about the file itself, e.g. that it's ignored, have no rule
+    // and no location.
+    #[serde(default)]
     pub rule_id: Value,
+    // Only set on fatal messages.
+    #[serde(default)]
     pub fatal: bool,
     pub severity: i64,
     pub message: String,
+    #[serde(default)]
     pub line: i64,
+    #[serde(default)]
     pub column: i64,
+    // Some messages, e.g. parse errors, have no end position.
+    #[serde(default)]
+    pub end_line: Option<i64>,
+    #[serde(default)]
+    pub end_column: Option<i64>,
+    #[serde(default)]
     pub node_type: Value,
 }
 
@@ -49,15 +70,23 @@ impl Plugin for EslintPlugin {
         "eslint"
     }
 
-    fn is_installed(&self, settings: DashMap<String, String>) -> Option<PluginSetting> {
+    fn local_binaries(&self) -> &[&str] {
+        &["node_modules/.bin/eslint_d", "node_modules/.bin/eslint"]
+    }
+
+    fn is_installed(
+        &self,
+        settings: DashMap<String, String>,
+        user_settings: &PluginSetting,
+    ) -> Option<PluginSetting> {
         let project_root = settings
             .get("root_uri")
             .expect("Cant fetch root uri")
             .to_string()
             .replace("file://", "");
 
-        let project_eslint = format!("{}/node_modules/.bin/eslint", project_root);
-        let default_args = vec!["-f=json".to_string()];
+        let mut default_args = vec!["-f=json".to_string()];
+        default_args.extend(config_args(user_settings, "--config="));
         let default_filetypes = vec![
             "js".to_string(),
             "tsx".to_string(),
@@ -65,13 +94,43 @@ impl Plugin for EslintPlugin {
             "svelte".to_string(),
         ];
 
-        info!("{project_eslint}");
-        if metadata(project_eslint.clone()).is_ok() {
+        // ESLint resolves its plugins relative to the working directory, so
+        // run it where the config is.
+        let working_dir = match config_style(&project_root) {
+            Some(style) => {
+                info!("ESLint {} config found in {}", style, project_root);
+                project_root.clone()
+            }
+            None => {
+                info!("No ESLint config found in {}", project_root);
+                "".to_string()
+            }
+        };
+
+        // Prefer the eslint_d daemon, it avoids the startup cost on every run.
+        let daemon = find_project_binary(&project_root, &["node_modules/.bin/eslint_d"])
+            .or_else(|| find_executable("eslint_d"));
+        if let Some(daemon) = daemon {
+            info!("Plugin ESLint found, using the eslint_d daemon");
+            return Some(PluginSetting {
+                cmd: daemon,
+                args: default_args,
+                filetypes: default_filetypes,
+                working_dir,
+                ..PluginSetting::default()
+            });
+        }
+
+        if let Some(project_eslint) =
+            find_project_binary(&project_root, &["node_modules/.bin/eslint"])
+        {
             info!("Plugin ESLint found");
             return Some(PluginSetting {
                 cmd: project_eslint,
                 args: default_args,
                 filetypes: default_filetypes,
+                working_dir,
+                ..PluginSetting::default()
             });
         }
 
@@ -79,78 +138,402 @@ impl Plugin for EslintPlugin {
         None
     }
 
-    async fn run(
+    fn rule_url(&self, rule: &str) -> Option<Url> {
+        // Only core and typescript-eslint rules have a predictable location.
+        if let Some(rule) = rule.strip_prefix("@typescript-eslint/") {
+            return Url::parse(&format!("https://typescript-eslint.io/rules/{}", rule)).ok();
+        }
+
+        if rule.contains('/') {
+            return None;
+        }
+
+        Url::parse(&format!("https://eslint.org/docs/latest/rules/{}", rule)).ok()
+    }
+
+    fn rule_tags(&self, rule: &str) -> Option<Vec<DiagnosticTag>> {
+        match rule {
+            "no-unused-vars"
+            | "no-unused-labels"
+            | "no-unused-private-class-members"
+            | "no-unreachable"
+            | "@typescript-eslint/no-unused-vars"
+            | "unused-imports/no-unused-imports"
+            | "unused-imports/no-unused-vars" => Some(vec![DiagnosticTag::UNNECESSARY]),
+            "import/no-deprecated"
+            | "deprecation/deprecation"
+            | "@typescript-eslint/no-deprecated" => Some(vec![DiagnosticTag::DEPRECATED]),
+            _ => None,
+        }
+    }
+
+    fn stdin_command(&self, plugin_settings: &PluginSetting, uri: &Url) -> Option<Vec<String>> {
+        let file = uri.to_string().replace("file://", "");
+        let mut args = stdin_args(plugin_settings, &file);
+        args.push("--stdin".to_string());
+        args.push(format!("--stdin-filename={}", file));
+        Some(args)
+    }
+
+    fn parse(
+        &self,
+        plugin_settings: &PluginSetting,
+        _uri: &Url,
+        stdout: &[u8],
+        stderr: &[u8],
+    ) -> Result<Vec<Diagnostic>, String> {
+        // ESLint may print deprecation notices to stderr next to a valid
+        // report, so stderr only counts when the report can't be parsed.
+        let report: EslintReport = match serde_json::from_slice(stdout) {
+            Ok(report) => report,
+            Err(_) if !stderr.is_empty() => {
+                return Err(String::from_utf8_lossy(stderr).to_string());
+            }
+            // No output at all means there is nothing to report.
+            Err(_) if stdout.iter().all(u8::is_ascii_whitespace) => EslintReport::default(),
+            Err(e) => return Err(format!("can't parse the report: {}", e)),
+        };
+
+        Ok(report_diagnostics(
+            &report,
+            plugin_settings,
+            self.column_base(),
+        ))
+    }
+
+    fn can_format(&self) -> bool {
+        true
+    }
+
+    async fn format(
         &self,
         plugin_settings: PluginSetting,
         uri: Url,
+        text: String,
         client: Client,
-    ) -> Option<PluginOutput> {
-        // Append file to args.
+    ) -> Option<String> {
         let file = uri.to_string().replace("file://", "");
-        let mut args = plugin_settings.args.clone();
-        args.push(file);
+        let mut args = stdin_args(&plugin_settings, &file);
+        args.push("--fix-dry-run".to_string());
+        args.push("--stdin".to_string());
+        args.push(format!("--stdin-filename={}", file));
 
         client
             .log_message(
                 MessageType::LOG,
-                format!("Running ESLInt with command {}", plugin_settings.cmd),
+                format!("Running ESLint --fix with command {}", plugin_settings.cmd),
             )
             .await;
 
-        let output = Command::new(plugin_settings.cmd)
-            .args(args)
-            .output()
-            .expect("failed to execute process");
+        let output = match run_with_stdin(
+            &plugin_settings.cmd,
+            args,
+            &plugin_settings.env,
+            &plugin_settings.working_dir,
+            &text,
+            plugin_settings.max_output_bytes,
+        )
+        .await
+        {
+            Ok(output) => output,
+            Err(e) => {
+                error!("ESLint --fix cant be executed: {}", e);
+                return None;
+            }
+        };
 
-        if !output.stderr.is_empty() {
-            error!(
-                "ESLint returned error: {}",
-                str::from_utf8(&output.stderr).unwrap()
-            );
+        let report: EslintReport = match serde_json::from_slice(&output.stdout) {
+            Ok(report) => report,
+            Err(_) => {
+                error!(
+                    "ESLint --fix returned error: {}",
+                    str::from_utf8(&output.stderr).unwrap_or_default()
+                );
+                return None;
+            }
+        };
 
-            return None;
-        }
+        // Without fixes, eslint leaves out the output.
+        let formatted = report
+            .into_iter()
+            .next()
+            .and_then(|file_report| file_report.output)
+            .unwrap_or(text);
 
-        let report: EslintReport = serde_json::from_slice(&output.stdout).unwrap_or_default();
-
-        for file_report in report {
-            let mut diagnostics = vec![];
-            for message in &file_report.messages {
-                let mut severity = DiagnosticSeverity::INFORMATION;
-
-                match &message.severity {
-                    1 => severity = DiagnosticSeverity::WARNING,
-                    2 => severity = DiagnosticSeverity::ERROR,
-                    _ => {}
-                }
-
-                let line_as_u32: u32 = message.line.try_into().unwrap();
-                let item = Diagnostic::new(
-                    Range::new(
-                        Position {
-                            line: line_as_u32 - 1,
-                            character: message.column.try_into().unwrap(),
-                        },
-                        Position {
-                            line: line_as_u32 - 1,
-                            character: message.column.try_into().unwrap(),
-                        },
-                    ),
-                    Some(severity),
-                    None,
-                    None,
-                    message.message.clone(),
-                    None,
-                    None,
-                );
+        Some(formatted)
+    }
+}
+
+// Config style of the project, "flat" for eslint.config.* (the default since
+// ESLint 9) and "legacy" for .eslintrc*.
+fn config_style(project_root: &str) -> Option<&'static str> {
+    let exists = |name: &str| Path::new(&format!("{}/{}", project_root, name)).is_file();
+
+    if ["eslint.config.js", "eslint.config.mjs", "eslint.config.cjs"]
+        .iter()
+        .any(|name| exists(name))
+    {
+        return Some("flat");
+    }
+
+    if [
+        ".eslintrc",
+        ".eslintrc.js",
+        ".eslintrc.cjs",
+        ".eslintrc.json",
+        ".eslintrc.yaml",
+        ".eslintrc.yml",
+    ]
+    .iter()
+    .any(|name| exists(name))
+    {
+        return Some("legacy");
+    }
+
+    None
+}
+
+// Turn the report into diagnostics, without running anything.
+fn report_diagnostics(
+    report: &EslintReport,
+    plugin_settings: &PluginSetting,
+    column_base: u32,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    for file_report in report {
+        for message in &file_report.messages {
+            let mut severity = DiagnosticSeverity::INFORMATION;
+
+            match &message.severity {
+                1 => severity = DiagnosticSeverity::WARNING,
+                2 => severity = DiagnosticSeverity::ERROR,
+                _ => {}
+            }
 
-                diagnostics.push(item);
+            // ESLint configs name the levels "warn" and "error".
+            let native = match &message.severity {
+                1 => "warning",
+                2 => "error",
+                _ => "info",
+            };
+            let mut severity = map_severity(plugin_settings, native, severity);
+
+            // Fatal messages, e.g. parse errors, have no rule and mean the
+            // file wasn't linted at all, so they're always errors. Other
+            // messages without a rule, e.g. about ignored files, keep theirs.
+            if message.fatal {
+                severity = DiagnosticSeverity::ERROR;
             }
+
+            let line_as_u32: u32 = message.line.try_into().unwrap_or(0);
+            let column_as_u32: u32 = message.column.try_into().unwrap_or(0);
+
+            // Without an end position a single character is underlined.
+            let (end_line_as_u32, end_column_as_u32) = match (message.end_line, message.end_column)
+            {
+                (Some(end_line), Some(end_column)) => (
+                    end_line.try_into().unwrap_or(line_as_u32),
+                    end_column.try_into().unwrap_or(column_as_u32),
+                ),
+                _ => (line_as_u32, column_as_u32.saturating_add(1)),
+            };
+
+            let item = Diagnostic::new(
+                Range::new(
+                    Position {
+                        line: to_zero_based(line_as_u32),
+                        character: to_character(column_as_u32, column_base),
+                    },
+                    Position {
+                        line: to_zero_based(end_line_as_u32),
+                        character: to_character(end_column_as_u32, column_base),
+                    },
+                ),
+                Some(severity),
+                message
+                    .rule_id
+                    .as_str()
+                    .map(|rule_id| NumberOrString::String(rule_id.to_string())),
+                None,
+                message.message.clone(),
+                None,
+                None,
+            );
+
+            diagnostics.push(item);
         }
+    }
 
-        client
-            .log_message(MessageType::LOG, "ESLint ended".to_string())
-            .await;
-        None
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, temp_dir, test_client, write_file};
+
+    fn parse(stdout: &[u8], stderr: &[u8]) -> Result<Vec<Diagnostic>, String> {
+        EslintPlugin.parse(
+            &PluginSetting::default(),
+            &Url::from_file_path("/project/src/app.js").unwrap(),
+            stdout,
+            stderr,
+        )
+    }
+
+    #[test]
+    fn parses_the_report() {
+        let diagnostics = parse(&fixture("eslint/report.json"), b"").unwrap();
+
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(2, 6), Position::new(2, 9))
+        );
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(
+            diagnostics[0].code,
+            Some(NumberOrString::String("no-unused-vars".to_string()))
+        );
+        assert_eq!(
+            diagnostics[1].range,
+            Range::new(Position::new(4, 13), Position::new(5, 0))
+        );
+        assert_eq!(diagnostics[1].severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(
+            diagnostics[1].code,
+            Some(NumberOrString::String("semi".to_string()))
+        );
+    }
+
+    #[test]
+    fn parse_errors_are_always_errors() {
+        // Even when the user maps ESLint errors to warnings.
+        let settings = PluginSetting {
+            severity_map: std::collections::HashMap::from([(
+                "error".to_string(),
+                "warning".to_string(),
+            )]),
+            ..PluginSetting::default()
+        };
+        let diagnostics = EslintPlugin
+            .parse(
+                &settings,
+                &Url::from_file_path("/project/src/app.js").unwrap(),
+                &fixture("eslint/parse-error.json"),
+                b"",
+            )
+            .unwrap();
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(diagnostics[0].code, None);
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(2, 16), Position::new(2, 17))
+        );
+    }
+
+    #[test]
+    fn parses_the_ignored_file_warning() {
+        let diagnostics = parse(&fixture("eslint/ignored.json"), b"").unwrap();
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(diagnostics[0].code, None);
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(0, 0), Position::new(0, 0))
+        );
+        assert!(diagnostics[0]
+            .message
+            .starts_with("File ignored because of a matching ignore pattern"));
+    }
+
+    #[test]
+    fn malformed_output_is_an_error() {
+        assert!(parse(b"Oops! Something went wrong!", b"").is_err());
+        assert_eq!(
+            parse(b"", b"Error: Cannot find module 'eslint-plugin-react'"),
+            Err("Error: Cannot find module 'eslint-plugin-react'".to_string())
+        );
+        assert_eq!(parse(b"", b""), Ok(vec![]));
+    }
+
+    #[test]
+    fn prefers_the_daemon() {
+        let root = temp_dir("eslint-daemon");
+        write_file(&root, "node_modules/.bin/eslint", "");
+        let daemon = write_file(&root, "node_modules/.bin/eslint_d", "");
+        let settings = DashMap::new();
+        settings.insert("root_uri".to_string(), format!("file://{}", root.display()));
+
+        let plugin_settings = EslintPlugin
+            .is_installed(settings, &PluginSetting::default())
+            .unwrap();
+        assert_eq!(plugin_settings.cmd, daemon.to_string_lossy());
+
+        // The daemon prints the same report, so it parses the same.
+        let uri = Url::from_file_path("/project/src/app.js").unwrap();
+        let eslint_settings = PluginSetting {
+            cmd: root
+                .join("node_modules/.bin/eslint")
+                .to_string_lossy()
+                .to_string(),
+            ..plugin_settings.clone()
+        };
+        assert_eq!(
+            EslintPlugin.parse(&plugin_settings, &uri, &fixture("eslint/report.json"), b""),
+            EslintPlugin.parse(&eslint_settings, &uri, &fixture("eslint/report.json"), b"")
+        );
+    }
+
+    #[test]
+    fn stderr_notices_next_to_a_report_are_ignored() {
+        let diagnostics = parse(
+            &fixture("eslint/report.json"),
+            &fixture("eslint/deprecation.stderr"),
+        )
+        .unwrap();
+
+        assert_eq!(diagnostics.len(), 2);
+    }
+
+    #[test]
+    fn lints_stdin_with_the_real_path() {
+        let settings = PluginSetting {
+            args: vec!["-f".to_string(), "json".to_string(), "{file}".to_string()],
+            ..PluginSetting::default()
+        };
+        let uri = Url::from_file_path("/project/src/app.js").unwrap();
+
+        assert_eq!(
+            EslintPlugin.stdin_command(&settings, &uri),
+            Some(vec![
+                "-f".to_string(),
+                "json".to_string(),
+                "--stdin".to_string(),
+                "--stdin-filename=/project/src/app.js".to_string(),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn parses_the_stdin_run() {
+        // ESLint is stood in for by a script echoing the report it's sent.
+        let settings = PluginSetting {
+            cmd: "sh".to_string(),
+            args: vec!["-c".to_string(), "cat".to_string(), "eslint".to_string()],
+            ..PluginSetting::default()
+        };
+        let uri = Url::from_file_path("/project/src/app.js").unwrap();
+        let report = String::from_utf8(fixture("eslint/report.json")).unwrap();
+
+        let output = EslintPlugin
+            .run_stdin(settings, uri.clone(), report, test_client())
+            .await
+            .unwrap();
+
+        assert_eq!(output.diagnostics[&uri].len(), 2);
     }
 }
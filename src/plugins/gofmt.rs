@@ -0,0 +1,109 @@
+use std::{format, str, vec};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use log::{error, info};
+use tower_lsp::lsp_types::{MessageType, Url};
+use tower_lsp::Client;
+
+use crate::plugins::{
+    find_executable, run_with_stdin, stdin_args, Plugin, PluginOutput, PluginSetting,
+};
+
+#[derive(Default)]
+pub struct GofmtPlugin;
+
+#[async_trait]
+impl Plugin for GofmtPlugin {
+    fn get_plugin_id(&self) -> &str {
+        "gofmt"
+    }
+
+    fn is_installed(
+        &self,
+        _settings: DashMap<String, String>,
+        _user_settings: &PluginSetting,
+    ) -> Option<PluginSetting> {
+        let default_filetypes = vec!["go".to_string()];
+
+        // goimports formats like gofmt, and fixes the imports as well.
+        let gofmt = find_executable("goimports").or_else(|| find_executable("gofmt"));
+        if let Some(gofmt) = gofmt {
+            info!("Plugin Gofmt found, using {}", gofmt);
+            return Some(PluginSetting {
+                cmd: gofmt,
+                args: vec![],
+                filetypes: default_filetypes,
+                ..PluginSetting::default()
+            });
+        }
+
+        error!("Gofmt cant be executed.");
+        None
+    }
+
+    async fn run(
+        &self,
+        _plugin_settings: PluginSetting,
+        _uri: Url,
+        _client: Client,
+    ) -> Option<PluginOutput> {
+        // Formatting only.
+        None
+    }
+
+    fn can_format(&self) -> bool {
+        true
+    }
+
+    async fn format(
+        &self,
+        plugin_settings: PluginSetting,
+        uri: Url,
+        text: String,
+        client: Client,
+    ) -> Option<String> {
+        // Without a file argument both tools format stdin to stdout.
+        let file = uri.to_string().replace("file://", "");
+        let mut args = stdin_args(&plugin_settings, &file);
+        if plugin_settings.cmd.ends_with("goimports") {
+            // Resolves the imports relative to the file's package.
+            args.push(format!("-srcdir={}", file));
+        }
+
+        client
+            .log_message(
+                MessageType::LOG,
+                format!("Running Gofmt with command {}", plugin_settings.cmd),
+            )
+            .await;
+
+        let output = match run_with_stdin(
+            &plugin_settings.cmd,
+            args,
+            &plugin_settings.env,
+            &plugin_settings.working_dir,
+            &text,
+            plugin_settings.max_output_bytes,
+        )
+        .await
+        {
+            Ok(output) => output,
+            Err(e) => {
+                error!("Gofmt cant be executed: {}", e);
+                return None;
+            }
+        };
+
+        // A syntax error leaves the buffer as it is.
+        if !output.status.success() {
+            error!(
+                "Gofmt returned error: {}",
+                str::from_utf8(&output.stderr).unwrap_or_default()
+            );
+            return None;
+        }
+
+        String::from_utf8(output.stdout).ok()
+    }
+}
@@ -0,0 +1,146 @@
+use std::{format, vec};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use log::{error, info};
+use tower_lsp::lsp_types::{Diagnostic, NumberOrString, Position, Range};
+use tower_lsp::lsp_types::{DiagnosticSeverity, Url};
+
+use crate::plugins::{
+    find_executable, map_severity, to_character, to_zero_based, Plugin, PluginSetting,
+};
+use serde_derive::Deserialize;
+
+type HadolintReport = Vec<Violation>;
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+struct Violation {
+    pub line: u32,
+    pub column: u32,
+    pub level: String,
+    pub code: String,
+    pub message: String,
+}
+
+#[derive(Default)]
+pub struct HadolintPlugin;
+
+#[async_trait]
+impl Plugin for HadolintPlugin {
+    fn get_plugin_id(&self) -> &str {
+        "hadolint"
+    }
+
+    fn is_installed(
+        &self,
+        _settings: DashMap<String, String>,
+        _user_settings: &PluginSetting,
+    ) -> Option<PluginSetting> {
+        let default_args = vec!["-f".to_string(), "json".to_string()];
+        // Dockerfiles usually have no extension, so they're matched by name.
+        let default_filetypes = vec!["Dockerfile".to_string(), "*.dockerfile".to_string()];
+
+        if let Some(hadolint) = find_executable("hadolint") {
+            info!("Plugin Hadolint found");
+            return Some(PluginSetting {
+                cmd: hadolint,
+                args: default_args,
+                filetypes: default_filetypes,
+                ..PluginSetting::default()
+            });
+        }
+
+        error!("Hadolint cant be executed.");
+        None
+    }
+
+    fn rule_url(&self, rule: &str) -> Option<Url> {
+        // SC rules come from ShellCheck, which checks the RUN instructions.
+        if rule.starts_with("SC") {
+            return Url::parse(&format!("https://www.shellcheck.net/wiki/{}", rule)).ok();
+        }
+
+        Url::parse(&format!(
+            "https://github.com/hadolint/hadolint/wiki/{}",
+            rule
+        ))
+        .ok()
+    }
+
+    fn parse(
+        &self,
+        plugin_settings: &PluginSetting,
+        _uri: &Url,
+        stdout: &[u8],
+        stderr: &[u8],
+    ) -> Result<Vec<Diagnostic>, String> {
+        let report: HadolintReport = match serde_json::from_slice(stdout) {
+            Ok(report) => report,
+            Err(_) if !stderr.is_empty() => {
+                return Err(String::from_utf8_lossy(stderr).to_string());
+            }
+            Err(e) => return Err(format!("can't parse the report: {}", e)),
+        };
+
+        Ok(report_diagnostics(
+            &report,
+            plugin_settings,
+            self.column_base(),
+        ))
+    }
+}
+
+// Turn the report into diagnostics, without running anything.
+fn report_diagnostics(
+    report: &HadolintReport,
+    plugin_settings: &PluginSetting,
+    column_base: u32,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    for violation in report {
+        let severity = match &violation.level[..] {
+            "error" => DiagnosticSeverity::ERROR,
+            "warning" => DiagnosticSeverity::WARNING,
+            "info" => DiagnosticSeverity::INFORMATION,
+            _ => DiagnosticSeverity::HINT,
+        };
+        let severity = map_severity(plugin_settings, &violation.level, severity);
+
+        let position = Position {
+            line: to_zero_based(violation.line),
+            character: to_character(violation.column, column_base),
+        };
+        diagnostics.push(Diagnostic::new(
+            Range::new(position, position),
+            Some(severity),
+            Some(NumberOrString::String(violation.code.clone())),
+            None,
+            violation.message.clone(),
+            None,
+            None,
+        ));
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPORT: &[u8] = br#"[{"line":3,"column":1,"level":"warning","code":"DL3008","message":"Pin versions in apt get install.","file":"Dockerfile"}]"#;
+
+    #[test]
+    fn converts_columns_by_the_column_base() {
+        let uri = Url::from_file_path("/project/Dockerfile").unwrap();
+        let diagnostics = HadolintPlugin
+            .parse(&PluginSetting::default(), &uri, REPORT, b"")
+            .unwrap();
+        assert_eq!(diagnostics[0].range.start, Position::new(2, 0));
+
+        // A tool counting columns from 0 keeps them as they are.
+        let report: HadolintReport = serde_json::from_slice(REPORT).unwrap();
+        let diagnostics = report_diagnostics(&report, &PluginSetting::default(), 0);
+        assert_eq!(diagnostics[0].range.start, Position::new(2, 1));
+    }
+}
@@ -0,0 +1,181 @@
+use std::{format, vec};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use log::{error, info};
+use tower_lsp::lsp_types::{Diagnostic, NumberOrString, Position, Range};
+use tower_lsp::lsp_types::{DiagnosticSeverity, Url};
+
+use crate::plugins::{
+    find_project_binary, map_severity, to_character, to_zero_based, Plugin, PluginSetting,
+};
+use serde_derive::Deserialize;
+
+type MarkuplintReport = Vec<Violation>;
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Violation {
+    pub severity: String,
+    pub message: String,
+    pub line: u32,
+    pub col: u32,
+    pub raw: String,
+    pub rule_id: String,
+    pub file_path: String,
+}
+
+#[derive(Default)]
+pub struct MarkuplintPlugin;
+
+#[async_trait]
+impl Plugin for MarkuplintPlugin {
+    fn get_plugin_id(&self) -> &str {
+        "markuplint"
+    }
+
+    fn local_binaries(&self) -> &[&str] {
+        &["node_modules/.bin/markuplint"]
+    }
+
+    fn is_installed(
+        &self,
+        settings: DashMap<String, String>,
+        _user_settings: &PluginSetting,
+    ) -> Option<PluginSetting> {
+        let project_root = settings
+            .get("root_uri")
+            .expect("Cant fetch root uri")
+            .to_string()
+            .replace("file://", "");
+
+        let default_args = vec!["--format".to_string(), "JSON".to_string()];
+        let default_filetypes = vec!["html".to_string()];
+
+        if let Some(markuplint) =
+            find_project_binary(&project_root, &["node_modules/.bin/markuplint"])
+        {
+            info!("Plugin Markuplint found");
+            return Some(PluginSetting {
+                cmd: markuplint,
+                args: default_args,
+                filetypes: default_filetypes,
+                ..PluginSetting::default()
+            });
+        }
+
+        error!("Markuplint cant be executed.");
+        None
+    }
+
+    fn rule_url(&self, rule: &str) -> Option<Url> {
+        Url::parse(&format!("https://markuplint.dev/docs/rules/{}", rule)).ok()
+    }
+
+    fn parse(
+        &self,
+        plugin_settings: &PluginSetting,
+        _uri: &Url,
+        stdout: &[u8],
+        stderr: &[u8],
+    ) -> Result<Vec<Diagnostic>, String> {
+        let report: MarkuplintReport = match serde_json::from_slice(stdout) {
+            Ok(report) => report,
+            Err(_) if !stderr.is_empty() => {
+                return Err(String::from_utf8_lossy(stderr).to_string());
+            }
+            Err(e) => return Err(format!("can't parse the report: {}", e)),
+        };
+
+        Ok(report_diagnostics(
+            &report,
+            plugin_settings,
+            self.column_base(),
+        ))
+    }
+}
+
+// Turn the report into diagnostics, without running anything.
+fn report_diagnostics(
+    report: &MarkuplintReport,
+    plugin_settings: &PluginSetting,
+    column_base: u32,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    for violation in report {
+        let severity = match &violation.severity[..] {
+            "error" => DiagnosticSeverity::ERROR,
+            "warning" => DiagnosticSeverity::WARNING,
+            _ => DiagnosticSeverity::INFORMATION,
+        };
+        let severity = map_severity(plugin_settings, &violation.severity, severity);
+
+        // The violation spans the raw source it was found in.
+        let character = to_character(violation.col, column_base);
+        let end_character = character + violation.raw.chars().count() as u32;
+        diagnostics.push(Diagnostic::new(
+            Range::new(
+                Position {
+                    line: to_zero_based(violation.line),
+                    character,
+                },
+                Position {
+                    line: to_zero_based(violation.line),
+                    character: end_character,
+                },
+            ),
+            Some(severity),
+            Some(NumberOrString::String(violation.rule_id.clone())),
+            None,
+            violation.message.clone(),
+            None,
+            None,
+        ));
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fixture;
+
+    fn parse(stdout: &[u8], stderr: &[u8]) -> Result<Vec<Diagnostic>, String> {
+        MarkuplintPlugin.parse(
+            &PluginSetting::default(),
+            &Url::from_file_path("/project/index.html").unwrap(),
+            stdout,
+            stderr,
+        )
+    }
+
+    #[test]
+    fn parses_the_report() {
+        let diagnostics = parse(&fixture("markuplint/report.json"), b"").unwrap();
+
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(11, 4), Position::new(11, 24))
+        );
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(
+            diagnostics[0].code,
+            Some(NumberOrString::String("required-attr".to_string()))
+        );
+        assert_eq!(
+            diagnostics[1].range,
+            Range::new(Position::new(19, 9), Position::new(19, 18))
+        );
+        assert_eq!(diagnostics[1].severity, Some(DiagnosticSeverity::WARNING));
+    }
+
+    #[test]
+    fn malformed_output_is_an_error() {
+        assert_eq!(
+            parse(b"", b"Error: Cannot find module 'markuplint'"),
+            Err("Error: Cannot find module 'markuplint'".to_string())
+        );
+    }
+}
@@ -0,0 +1,231 @@
+use std::{
+    format, str,
+    time::{SystemTime, UNIX_EPOCH},
+    vec,
+};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use log::{error, info};
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, MessageType, Position, Range, Url};
+use tower_lsp::Client;
+
+use crate::plugins::{
+    build_args, execute, execute_plugin, find_executable, find_project_binary, log_execute_error,
+    map_severity, Plugin, PluginOutput, PluginSetting,
+};
+use serde_derive::Deserialize;
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+struct PhpCsFixerReport {
+    pub files: Vec<FileReport>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FileReport {
+    pub name: String,
+    pub applied_fixers: Vec<String>,
+}
+
+#[derive(Default)]
+pub struct PhpCsFixerPlugin;
+
+#[async_trait]
+impl Plugin for PhpCsFixerPlugin {
+    fn get_plugin_id(&self) -> &str {
+        "php-cs-fixer"
+    }
+
+    fn local_binaries(&self) -> &[&str] {
+        &["vendor/bin/php-cs-fixer"]
+    }
+
+    fn is_installed(
+        &self,
+        settings: DashMap<String, String>,
+        _user_settings: &PluginSetting,
+    ) -> Option<PluginSetting> {
+        let project_root = settings
+            .get("root_uri")
+            .expect("Cant fetch root uri")
+            .to_string()
+            .replace("file://", "");
+
+        let default_args = vec![
+            "fix".to_string(),
+            "--dry-run".to_string(),
+            "--format=json".to_string(),
+        ];
+        let default_filetypes = vec!["php".to_string()];
+
+        let php_cs_fixer = find_project_binary(&project_root, &["vendor/bin/php-cs-fixer"])
+            .or_else(|| find_executable("php-cs-fixer"));
+        if let Some(php_cs_fixer) = php_cs_fixer {
+            info!("Plugin PHP CS Fixer found");
+            return Some(PluginSetting {
+                cmd: php_cs_fixer,
+                args: default_args,
+                filetypes: default_filetypes,
+                ..PluginSetting::default()
+            });
+        }
+
+        error!("PHP CS Fixer cant be executed.");
+        None
+    }
+
+    async fn run(
+        &self,
+        plugin_settings: PluginSetting,
+        uri: Url,
+        client: Client,
+    ) -> Option<PluginOutput> {
+        let file = uri.to_string().replace("file://", "");
+        let args = build_args(&plugin_settings, &file);
+
+        client
+            .log_message(
+                MessageType::LOG,
+                format!("Running PHP CS Fixer with command {}", plugin_settings.cmd),
+            )
+            .await;
+
+        let output = match execute_plugin(&plugin_settings, args).await {
+            Ok(output) => output,
+            Err(e) => {
+                log_execute_error(&client, &plugin_settings.cmd, e).await;
+                return None;
+            }
+        };
+
+        // A dry run exits with 8 when the file needs fixing, so only the
+        // report matters.
+        let report: PhpCsFixerReport = match serde_json::from_slice(&output.stdout) {
+            Ok(report) => report,
+            Err(_) => {
+                error!(
+                    "PHP CS Fixer returned error: {}",
+                    str::from_utf8(&output.stderr).unwrap_or_default()
+                );
+                return None;
+            }
+        };
+
+        // The report has no positions, so the fixers are reported on the
+        // first line of the file.
+        let mut plugin_output = PluginOutput::default();
+        let diagnostics = plugin_output.diagnostics.entry(uri.clone()).or_default();
+        for file_report in report.files {
+            if file_report.applied_fixers.is_empty() {
+                continue;
+            }
+
+            info!("PHP CS Fixer would fix {}", file_report.name);
+            diagnostics.push(Diagnostic::new(
+                Range::new(
+                    Position {
+                        line: 0,
+                        character: 0,
+                    },
+                    Position {
+                        line: 0,
+                        character: 0,
+                    },
+                ),
+                Some(map_severity(
+                    &plugin_settings,
+                    "warning",
+                    DiagnosticSeverity::WARNING,
+                )),
+                None,
+                None,
+                format!(
+                    "Fixable by php-cs-fixer: {}",
+                    file_report.applied_fixers.join(", ")
+                ),
+                None,
+                None,
+            ));
+        }
+
+        client
+            .log_message(MessageType::LOG, "PHP CS Fixer ended".to_string())
+            .await;
+        Some(plugin_output)
+    }
+
+    fn can_format(&self) -> bool {
+        true
+    }
+
+    async fn format(
+        &self,
+        plugin_settings: PluginSetting,
+        _uri: Url,
+        text: String,
+        client: Client,
+    ) -> Option<String> {
+        // PHP CS Fixer can't print the fixed source, so the buffer is fixed
+        // in a temporary file instead.
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_nanos())
+            .unwrap_or_default();
+        let tmp_file = std::env::temp_dir().join(format!("checkmate-php-cs-fixer-{}.php", nanos));
+        let tmp_file = tmp_file.to_string_lossy().to_string();
+
+        if let Err(e) = tokio::fs::write(&tmp_file, &text).await {
+            error!("Cant write the PHP CS Fixer buffer: {}", e);
+            return None;
+        }
+
+        let fix_settings = PluginSetting {
+            args: plugin_settings
+                .args
+                .iter()
+                .filter(|arg| *arg != "--dry-run")
+                .cloned()
+                .chain(vec!["--using-cache=no".to_string()])
+                .collect(),
+            ..plugin_settings.clone()
+        };
+        let args = build_args(&fix_settings, &tmp_file);
+
+        client
+            .log_message(
+                MessageType::LOG,
+                format!(
+                    "Running PHP CS Fixer fix with command {}",
+                    plugin_settings.cmd
+                ),
+            )
+            .await;
+
+        let output = execute(
+            &plugin_settings.cmd,
+            args,
+            &plugin_settings.env,
+            &plugin_settings.working_dir,
+            plugin_settings.max_output_bytes,
+        )
+        .await;
+        let fixed = tokio::fs::read_to_string(&tmp_file).await;
+        let _ = tokio::fs::remove_file(&tmp_file).await;
+
+        match output {
+            Ok(output) if output.status.success() => fixed.ok(),
+            Ok(output) => {
+                error!(
+                    "PHP CS Fixer returned error: {}",
+                    str::from_utf8(&output.stderr).unwrap_or_default()
+                );
+                None
+            }
+            Err(e) => {
+                error!("PHP CS Fixer cant be executed: {}", e);
+                None
+            }
+        }
+    }
+}
@@ -1,13 +1,16 @@
-use std::{collections::HashMap, format, fs::metadata, process::Command, str, vec};
+use std::{collections::HashMap, format, fs::metadata, str, vec};
 
 use async_trait::async_trait;
 use dashmap::DashMap;
 use log::{error, info};
-use tower_lsp::lsp_types::{Diagnostic, Position, Range};
-use tower_lsp::lsp_types::{DiagnosticSeverity, MessageType, Url};
+use tower_lsp::lsp_types::{Diagnostic, NumberOrString, Position, Range};
+use tower_lsp::lsp_types::{DiagnosticSeverity, DiagnosticTag, MessageType, Url};
 use tower_lsp::Client;
 
-use crate::plugins::{Plugin, PluginOutput, PluginSetting};
+use crate::plugins::{
+    config_args, find_binary, find_executable, is_report_file, map_severity, run_with_stdin,
+    stdin_args, to_character, to_zero_based, Plugin, PluginSetting,
+};
 use serde_derive::Deserialize;
 
 #[derive(Default, Debug, Clone, PartialEq, Deserialize)]
@@ -46,125 +49,284 @@ impl Plugin for PhpcsPlugin {
         "phpcs"
     }
 
-    fn is_installed(&self, settings: DashMap<String, String>) -> Option<PluginSetting> {
+    fn local_binaries(&self) -> &[&str] {
+        &["vendor/bin/phpcs"]
+    }
+
+    fn is_installed(
+        &self,
+        settings: DashMap<String, String>,
+        user_settings: &PluginSetting,
+    ) -> Option<PluginSetting> {
         let project_root = settings
             .get("root_uri")
             .expect("Cant fetch root uri")
             .to_string()
             .replace("file://", "");
 
-        let project_phpcs = format!("{}/vendor/bin/phpcs", project_root);
-        let default_args = vec!["--report=json".to_string()];
+        let mut default_args = vec!["--report=json".to_string()];
+        default_args.extend(config_args(user_settings, "--standard="));
+        if !user_settings.standard.is_empty() && user_settings.config_path.is_empty() {
+            default_args.push(format!("--standard={}", user_settings.standard));
+        }
         let default_filetypes = vec!["php".to_string()];
 
-        if metadata(project_phpcs.clone()).is_ok() {
-            info!("Plugin Phpcs found");
+        if let Some(phpcs) = find_binary(&project_root, self.local_binaries(), "phpcs") {
+            info!("Plugin Phpcs found, using {}", phpcs);
             return Some(PluginSetting {
-                cmd: project_phpcs,
+                cmd: phpcs,
                 args: default_args,
                 filetypes: default_filetypes,
+                ..PluginSetting::default()
             });
         }
 
-        info!("Project PHPCS not found, trying global ...");
+        error!("Phpcs cant be executed.");
+        None
+    }
 
-        match Command::new("phpcs").spawn() {
-            Ok(_) => Some(PluginSetting {
-                cmd: "phpcs".to_string(),
-                args: default_args,
-                filetypes: default_filetypes,
-            }),
-            Err(e) => {
-                if let std::io::ErrorKind::NotFound = e.kind() {
-                    error!("Global PHPCS not found");
-                    return None;
-                }
+    fn rule_tags(&self, rule: &str) -> Option<Vec<DiagnosticTag>> {
+        // Sniff names follow Standard.Category.Sniff.Code, e.g.
+        // "SlevomatCodingStandard.Namespaces.UnusedUses.UnusedUse".
+        if rule.contains("Unused") {
+            return Some(vec![DiagnosticTag::UNNECESSARY]);
+        }
 
-                error!("Global PHPCS cant be executed.");
-                None
-            }
+        if rule.contains("Deprecated") {
+            return Some(vec![DiagnosticTag::DEPRECATED]);
+        }
+
+        None
+    }
+
+    fn stdin_command(&self, plugin_settings: &PluginSetting, uri: &Url) -> Option<Vec<String>> {
+        let file = uri.to_string().replace("file://", "");
+        let mut args = stdin_args(plugin_settings, &file);
+        args.push(format!("--stdin-path={}", file));
+        args.push("-".to_string());
+        Some(args)
+    }
+
+    fn parse(
+        &self,
+        plugin_settings: &PluginSetting,
+        uri: &Url,
+        stdout: &[u8],
+        stderr: &[u8],
+    ) -> Result<Vec<Diagnostic>, String> {
+        if !stderr.is_empty() {
+            return Err(String::from_utf8_lossy(stderr).to_string());
         }
+
+        let report: PhpcsReport =
+            serde_json::from_slice(stdout).map_err(|e| format!("can't parse the report: {}", e))?;
+        Ok(report_diagnostics(
+            &report,
+            plugin_settings,
+            uri,
+            self.column_base(),
+        ))
     }
 
-    async fn run(
+    fn can_format(&self) -> bool {
+        true
+    }
+
+    async fn format(
         &self,
         plugin_settings: PluginSetting,
         uri: Url,
+        text: String,
         client: Client,
-    ) -> Option<PluginOutput> {
-        // Append file to args.
+    ) -> Option<String> {
+        // Phpcbf ships next to phpcs.
+        let phpcbf = match plugin_settings.cmd.strip_suffix("phpcs") {
+            Some(prefix) if metadata(format!("{}phpcbf", prefix)).is_ok() => {
+                format!("{}phpcbf", prefix)
+            }
+            _ => find_executable("phpcbf")?,
+        };
+
+        // Phpcbf prints the fixed file, so drop the report flags.
         let file = uri.to_string().replace("file://", "");
-        let mut args = plugin_settings.args.clone();
-        args.push(file);
+        let mut args: Vec<String> = stdin_args(&plugin_settings, &file)
+            .into_iter()
+            .filter(|arg| !arg.starts_with("--report"))
+            .collect();
+        args.push("-q".to_string());
+        args.push(format!("--stdin-path={}", file));
+        args.push("-".to_string());
 
         client
             .log_message(
                 MessageType::LOG,
-                format!("Running PHPCS with command {}", plugin_settings.cmd),
+                format!("Running PHPCBF with command {}", phpcbf),
             )
             .await;
 
-        let output = Command::new(plugin_settings.cmd)
-            .args(args)
-            .output()
-            .expect("failed to execute process");
-
-        if !output.stderr.is_empty() {
-            client
-                .log_message(
-                    MessageType::ERROR,
-                    format!(
-                        "PHPCS returned error: {}",
-                        str::from_utf8(&output.stderr).unwrap(),
-                    ),
-                )
-                .await;
+        let output = match run_with_stdin(
+            &phpcbf,
+            args,
+            &plugin_settings.env,
+            &plugin_settings.working_dir,
+            &text,
+            plugin_settings.max_output_bytes,
+        )
+        .await
+        {
+            Ok(output) => output,
+            Err(e) => {
+                error!("PHPCBF cant be executed: {}", e);
+                return None;
+            }
+        };
+
+        // Exit code 3 means phpcbf itself failed.
+        if output.status.code() == Some(3) || output.stdout.is_empty() {
+            error!(
+                "PHPCBF returned error: {}",
+                str::from_utf8(&output.stderr).unwrap_or_default()
+            );
             return None;
         }
 
-        let report: PhpcsReport = serde_json::from_slice(&output.stdout).unwrap_or_default();
-
-        for file_report in report.files.values() {
-            let mut diagnostics = vec![];
-            for message in &file_report.messages {
-                let mut severity = DiagnosticSeverity::INFORMATION;
-
-                match &message.type_field[..] {
-                    "WARNING" => severity = DiagnosticSeverity::WARNING,
-                    "ERROR" => severity = DiagnosticSeverity::ERROR,
-                    _ => {}
-                }
-
-                let item = Diagnostic::new(
-                    Range::new(
-                        Position {
-                            line: message.line - 1,
-                            character: message.column,
-                        },
-                        Position {
-                            line: message.line - 1,
-                            character: message.column,
-                        },
-                    ),
-                    Some(severity),
-                    None,
-                    None,
-                    message.message.clone(),
-                    None,
-                    None,
-                );
-
-                diagnostics.push(item);
+        String::from_utf8(output.stdout).ok()
+    }
+}
+
+// Turn the report into diagnostics of the saved file, without running anything.
+fn report_diagnostics(
+    report: &PhpcsReport,
+    plugin_settings: &PluginSetting,
+    uri: &Url,
+    column_base: u32,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    for (path, file_report) in &report.files {
+        // Only the saved file's results belong to its uri.
+        if !is_report_file(path, &plugin_settings.root, uri) {
+            continue;
+        }
+
+        for message in &file_report.messages {
+            let mut severity = DiagnosticSeverity::INFORMATION;
+
+            match &message.type_field[..] {
+                "WARNING" => severity = DiagnosticSeverity::WARNING,
+                "ERROR" => severity = DiagnosticSeverity::ERROR,
+                _ => {}
             }
+            let severity = map_severity(plugin_settings, &message.type_field, severity);
+
+            let item = Diagnostic::new(
+                Range::new(
+                    Position {
+                        line: to_zero_based(message.line),
+                        character: to_character(message.column, column_base),
+                    },
+                    Position {
+                        line: to_zero_based(message.line),
+                        character: to_character(message.column, column_base),
+                    },
+                ),
+                Some(severity),
+                Some(NumberOrString::String(message.source.clone())),
+                None,
+                message.message.clone(),
+                None,
+                None,
+            );
 
-            client
-                .publish_diagnostics(uri.clone(), diagnostics, Some(1))
-                .await;
+            diagnostics.push(item);
         }
+    }
 
-        client
-            .log_message(MessageType::LOG, "PHPCS ended".to_string())
-            .await;
-        None
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fixture;
+
+    fn uri(path: &str) -> Url {
+        Url::from_file_path(path).unwrap()
+    }
+
+    #[test]
+    fn parses_the_saved_files_messages() {
+        let diagnostics = PhpcsPlugin
+            .parse(
+                &PluginSetting::default(),
+                &uri("/project/src/Foo.php"),
+                &fixture("phpcs/report.json"),
+                b"",
+            )
+            .unwrap();
+
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(4, 0), Position::new(4, 0))
+        );
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(
+            diagnostics[0].code,
+            Some(NumberOrString::String(
+                "Squiz.Commenting.ClassComment.Missing".to_string()
+            ))
+        );
+        assert_eq!(diagnostics[1].range.start, Position::new(11, 134));
+        assert_eq!(diagnostics[1].severity, Some(DiagnosticSeverity::WARNING));
+    }
+
+    #[test]
+    fn skips_other_files() {
+        let diagnostics = PhpcsPlugin
+            .parse(
+                &PluginSetting::default(),
+                &uri("/project/src/Baz.php"),
+                &fixture("phpcs/report.json"),
+                b"",
+            )
+            .unwrap();
+
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn malformed_output_is_an_error() {
+        let settings = PluginSetting::default();
+        let uri = uri("/project/src/Foo.php");
+
+        assert!(PhpcsPlugin
+            .parse(&settings, &uri, b"PHP Fatal error: Uncaught", b"")
+            .is_err());
+        assert_eq!(
+            PhpcsPlugin.parse(
+                &settings,
+                &uri,
+                b"",
+                b"ERROR: the standard is not installed"
+            ),
+            Err("ERROR: the standard is not installed".to_string())
+        );
+    }
+
+    #[test]
+    fn lints_stdin_with_the_real_path() {
+        let settings = PluginSetting {
+            args: vec!["--report=json".to_string()],
+            ..PluginSetting::default()
+        };
+
+        assert_eq!(
+            PhpcsPlugin.stdin_command(&settings, &uri("/project/src/Foo.php")),
+            Some(vec![
+                "--report=json".to_string(),
+                "--stdin-path=/project/src/Foo.php".to_string(),
+                "-".to_string(),
+            ])
+        );
     }
 }
@@ -0,0 +1,143 @@
+use std::{format, str, vec};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use log::{error, info};
+use tower_lsp::lsp_types::{CodeDescription, Diagnostic, NumberOrString, Position, Range};
+use tower_lsp::lsp_types::{DiagnosticSeverity, Url};
+
+use crate::plugins::{
+    find_executable, find_project_binary, map_severity, to_zero_based, Plugin, PluginSetting,
+};
+use serde_derive::Deserialize;
+
+const DEFAULT_RULESETS: &str = "cleancode,codesize,controversial,design,naming,unusedcode";
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+struct PhpmdReport {
+    #[serde(default)]
+    pub files: Vec<FileReport>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+struct FileReport {
+    pub file: String,
+    pub violations: Vec<Violation>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Violation {
+    pub begin_line: u32,
+    pub rule: String,
+    pub rule_set: String,
+    pub priority: u32,
+    pub description: String,
+    #[serde(default)]
+    pub external_info_url: String,
+}
+
+#[derive(Default)]
+pub struct PhpmdPlugin;
+
+#[async_trait]
+impl Plugin for PhpmdPlugin {
+    fn get_plugin_id(&self) -> &str {
+        "phpmd"
+    }
+
+    fn local_binaries(&self) -> &[&str] {
+        &["vendor/bin/phpmd"]
+    }
+
+    fn is_installed(
+        &self,
+        settings: DashMap<String, String>,
+        user_settings: &PluginSetting,
+    ) -> Option<PluginSetting> {
+        let project_root = settings
+            .get("root_uri")
+            .expect("Cant fetch root uri")
+            .to_string()
+            .replace("file://", "");
+
+        // The file goes first, the rulesets (or a ruleset.xml set as
+        // config_path) last.
+        let rulesets = if user_settings.config_path.is_empty() {
+            DEFAULT_RULESETS.to_string()
+        } else {
+            "{config}".to_string()
+        };
+        let default_args = vec!["{file}".to_string(), "json".to_string(), rulesets];
+        let default_filetypes = vec!["php".to_string()];
+
+        let phpmd = find_project_binary(&project_root, &["vendor/bin/phpmd"])
+            .or_else(|| find_executable("phpmd"));
+        if let Some(phpmd) = phpmd {
+            info!("Plugin Phpmd found");
+            return Some(PluginSetting {
+                cmd: phpmd,
+                args: default_args,
+                filetypes: default_filetypes,
+                ..PluginSetting::default()
+            });
+        }
+
+        error!("Phpmd cant be executed.");
+        None
+    }
+
+    fn parse(
+        &self,
+        plugin_settings: &PluginSetting,
+        _uri: &Url,
+        stdout: &[u8],
+        stderr: &[u8],
+    ) -> Result<Vec<Diagnostic>, String> {
+        // Phpmd exits with 2 when it finds violations, so only the report matters.
+        let report: PhpmdReport = serde_json::from_slice(stdout)
+            .map_err(|_| String::from_utf8_lossy(stderr).to_string())?;
+
+        let mut diagnostics = vec![];
+        for file_report in report.files {
+            info!(
+                "Phpmd found {} violations in {}",
+                file_report.violations.len(),
+                file_report.file
+            );
+
+            for violation in file_report.violations {
+                // Priority runs from 1 (highest) to 5.
+                let severity = match violation.priority {
+                    1 | 2 => DiagnosticSeverity::ERROR,
+                    3 => DiagnosticSeverity::WARNING,
+                    4 => DiagnosticSeverity::INFORMATION,
+                    _ => DiagnosticSeverity::HINT,
+                };
+                let severity =
+                    map_severity(plugin_settings, &violation.priority.to_string(), severity);
+
+                // Violations can span a whole method, only the first line is marked.
+                let position = Position {
+                    line: to_zero_based(violation.begin_line),
+                    character: 0,
+                };
+                let mut item = Diagnostic::new(
+                    Range::new(position, position),
+                    Some(severity),
+                    Some(NumberOrString::String(violation.rule.clone())),
+                    None,
+                    format!("{} ({})", violation.description.trim(), violation.rule_set),
+                    None,
+                    None,
+                );
+                item.code_description = Url::parse(&violation.external_info_url)
+                    .ok()
+                    .map(|href| CodeDescription { href });
+                diagnostics.push(item);
+            }
+        }
+
+        Ok(diagnostics)
+    }
+}
@@ -1,14 +1,17 @@
-use std::{collections::HashMap, fs::metadata, process::Command, str};
+use std::{collections::HashMap, fs::metadata};
 
 use async_trait::async_trait;
 use dashmap::DashMap;
 use log::{error, info};
 use serde_derive::Deserialize;
-use tower_lsp::lsp_types::{Diagnostic, MessageType, Position, Range};
+use tower_lsp::lsp_types::{Diagnostic, MessageType, NumberOrString, Position, Range};
 use tower_lsp::lsp_types::{DiagnosticSeverity, Url};
 use tower_lsp::Client;
 
-use crate::plugins::{Plugin, PluginOutput, PluginSetting};
+use crate::plugins::{
+    build_args, config_args, execute_plugin, find_binary, is_report_file, log_execute_error,
+    map_severity, report_uri, substitute_args, to_zero_based, Plugin, PluginOutput, PluginSetting,
+};
 
 #[derive(Default)]
 pub struct PhpstanPlugin;
@@ -16,7 +19,11 @@ pub struct PhpstanPlugin;
 #[derive(Default, Debug, Clone, PartialEq, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct PhpstanReport {
+    #[serde(default)]
     pub files: HashMap<String, FileReport>,
+    // Errors not tied to a file, e.g. config problems or internal errors.
+    #[serde(default)]
+    pub errors: Vec<String>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Deserialize)]
@@ -29,7 +36,14 @@ struct FileReport {
 #[serde(rename_all = "camelCase")]
 struct FileMessage {
     pub message: String,
-    pub line: u32,
+    // Null for errors about the whole file.
+    #[serde(default)]
+    pub line: Option<u32>,
+    #[serde(default)]
+    pub tip: Option<String>,
+    // Error identifier, e.g. "argument.type", since PHPStan 1.11.
+    #[serde(default)]
+    pub identifier: Option<String>,
 }
 
 #[async_trait]
@@ -38,44 +52,55 @@ impl Plugin for PhpstanPlugin {
         "phpstan"
     }
 
-    fn is_installed(&self, settings: DashMap<String, String>) -> Option<PluginSetting> {
+    fn local_binaries(&self) -> &[&str] {
+        &["vendor/bin/phpstan"]
+    }
+
+    fn is_installed(
+        &self,
+        settings: DashMap<String, String>,
+        user_settings: &PluginSetting,
+    ) -> Option<PluginSetting> {
         let project_root = settings
             .get("root_uri")
             .expect("Cant fetch root uri")
             .to_string()
             .replace("file://", "");
 
-        let project_phpstan = format!("{}/vendor/bin/phpstan", project_root);
-        let default_args = vec!["analyse".to_string(), "--error-format=json".to_string()];
+        let mut default_args = vec!["analyse".to_string(), "--error-format=json".to_string()];
         let default_filetypes = vec!["php".to_string()];
 
-        if metadata(project_phpstan.clone()).is_ok() {
-            info!("Plugin Phpstan found");
+        // A config of its own replaces the baseline only config.
+        let config_args = config_args(user_settings, "--configuration=");
+        if !config_args.is_empty() {
+            default_args.extend(config_args);
+        } else if user_settings.use_baseline {
+            if let Some(baseline_args) = baseline_args(&project_root) {
+                info!("Phpstan baseline found, passing it via {:?}", baseline_args);
+                default_args.extend(baseline_args);
+            }
+        }
+
+        if let Some(phpstan) = find_binary(&project_root, self.local_binaries(), "phpstan") {
+            info!("Plugin Phpstan found, using {}", phpstan);
             return Some(PluginSetting {
-                cmd: project_phpstan,
+                cmd: phpstan,
                 args: default_args,
                 filetypes: default_filetypes,
+                ..PluginSetting::default()
             });
         }
 
-        info!("Project Phpstan not found, trying global ...");
+        error!("Phpstan cant be executed.");
+        None
+    }
 
-        match Command::new("phpstan").spawn() {
-            Ok(_) => Some(PluginSetting {
-                cmd: "phpstan".to_string(),
-                args: default_args,
-                filetypes: default_filetypes,
-            }),
-            Err(e) => {
-                if let std::io::ErrorKind::NotFound = e.kind() {
-                    error!("Global Phpstan not found");
-                    return None;
-                }
+    fn is_project_scoped(&self) -> bool {
+        true
+    }
 
-                error!("Global Phpstan cant be executed.");
-                None
-            }
-        }
+    fn rule_url(&self, rule: &str) -> Option<Url> {
+        Url::parse(&format!("https://phpstan.org/error-identifiers/{}", rule)).ok()
     }
 
     async fn run(
@@ -84,10 +109,17 @@ impl Plugin for PhpstanPlugin {
         uri: Url,
         client: Client,
     ) -> Option<PluginOutput> {
-        // Append file to args.
         let file = uri.to_string().replace("file://", "");
-        let mut args = plugin_settings.args.clone();
-        args.push(file);
+
+        // Analysing the configured paths gives the right cross-file results,
+        // and keeps the result cache warm.
+        let args = if plugin_settings.analyse_paths.is_empty() {
+            build_args(&plugin_settings, &file)
+        } else {
+            let mut args = substitute_args(&plugin_settings, &file);
+            args.extend(plugin_settings.analyse_paths.clone());
+            args
+        };
 
         client
             .log_message(
@@ -96,46 +128,246 @@ impl Plugin for PhpstanPlugin {
             )
             .await;
 
-        let output = Command::new(plugin_settings.cmd)
-            .args(args)
-            .output()
-            .expect("failed to execute process");
-
-        let report: PhpstanReport = serde_json::from_slice(&output.stdout).unwrap_or_default();
-
-        for file_report in report.files.values() {
-            let mut diagnostics = vec![];
-            for message in &file_report.messages {
-                let item = Diagnostic::new(
-                    Range::new(
-                        Position {
-                            line: message.line - 1,
-                            character: 1,
-                        },
-                        Position {
-                            line: message.line - 1,
-                            character: 1,
-                        },
-                    ),
-                    Some(DiagnosticSeverity::ERROR),
-                    None,
-                    None,
-                    message.message.clone(),
-                    None,
-                    None,
-                );
-
-                diagnostics.push(item);
+        let output = match execute_plugin(&plugin_settings, args).await {
+            Ok(output) => output,
+            Err(e) => {
+                log_execute_error(&client, &plugin_settings.cmd, e).await;
+                return None;
             }
+        };
 
+        let report = match parse_report(&output.stdout, &output.stderr) {
+            Ok(report) => report,
+            Err(e) => {
+                let message = format!("PHPSTAN returned error: {}", e);
+                error!("{}", message);
+                client.log_message(MessageType::ERROR, message).await;
+
+                return None;
+            }
+        };
+
+        // Without these a failing phpstan would look like a clean file.
+        for report_error in &report.errors {
+            let message = format!("PHPSTAN returned error: {}", report_error);
+            error!("{}", message);
             client
-                .publish_diagnostics(uri.clone(), diagnostics, Some(1))
+                .log_message(MessageType::ERROR, message.clone())
                 .await;
+            client.show_message(MessageType::ERROR, message).await;
         }
 
+        let plugin_output = PluginOutput {
+            diagnostics: report_diagnostics(&report, &plugin_settings, &uri),
+        };
+
         client
             .log_message(MessageType::LOG, "PHPSTAN ended".to_string())
             .await;
-        None
+        Some(plugin_output)
+    }
+}
+
+// PHPStan prints progress and notices to stderr next to a valid report, so
+// stderr only counts when the report can't be parsed. The PHP CLI prints
+// fatal errors, e.g. running out of memory, to stdout instead of the report.
+fn parse_report(stdout: &[u8], stderr: &[u8]) -> Result<PhpstanReport, String> {
+    match serde_json::from_slice(stdout) {
+        Ok(report) => Ok(report),
+        // No output at all means there is nothing to report.
+        Err(_) if stdout.iter().all(u8::is_ascii_whitespace) => Ok(PhpstanReport::default()),
+        Err(_) => {
+            let output: Vec<String> = [stdout, stderr]
+                .iter()
+                .map(|output| String::from_utf8_lossy(output).trim().to_string())
+                .filter(|output| !output.is_empty())
+                .collect();
+            Err(output.join("\n"))
+        }
+    }
+}
+
+// Turn the report into diagnostics per file, without running anything.
+fn report_diagnostics(
+    report: &PhpstanReport,
+    plugin_settings: &PluginSetting,
+    uri: &Url,
+) -> HashMap<Url, Vec<Diagnostic>> {
+    let mut diagnostics: HashMap<Url, Vec<Diagnostic>> = HashMap::new();
+    for (path, file_report) in &report.files {
+        // Errors in traits are reported as "Trait.php (in context of class X)".
+        let path = path.split(" (in context of").next().unwrap_or(path);
+
+        // PHPStan analyses the whole project, results of other files are
+        // published under their own uri. With analyse_paths the whole paths
+        // are reported, so only the saved file is shown.
+        let file_uri = if is_report_file(path, &plugin_settings.root, uri) {
+            uri.clone()
+        } else if !plugin_settings.analyse_paths.is_empty() {
+            continue;
+        } else {
+            match report_uri(path, &plugin_settings.root) {
+                Some(file_uri) => file_uri,
+                None => continue,
+            }
+        };
+
+        diagnostics
+            .entry(file_uri)
+            .or_default()
+            .extend(file_diagnostics(file_report, plugin_settings));
+    }
+
+    diagnostics
+}
+
+// Turn the messages of one file into diagnostics, without running anything.
+fn file_diagnostics(file_report: &FileReport, plugin_settings: &PluginSetting) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    for message in &file_report.messages {
+        // Tips have no location of their own, so they go with the message.
+        let mut text = message.message.clone();
+        if let Some(tip) = &message.tip {
+            text.push_str(&format!("\nTip: {}", tip));
+        }
+
+        let item = Diagnostic::new(
+            Range::new(
+                Position {
+                    line: to_zero_based(message.line.unwrap_or(0)),
+                    character: 0,
+                },
+                Position {
+                    line: to_zero_based(message.line.unwrap_or(0)),
+                    character: 0,
+                },
+            ),
+            Some(map_severity(
+                plugin_settings,
+                "error",
+                DiagnosticSeverity::ERROR,
+            )),
+            message
+                .identifier
+                .as_ref()
+                .map(|identifier| NumberOrString::String(identifier.clone())),
+            None,
+            text,
+            None,
+            None,
+        );
+
+        diagnostics.push(item);
+    }
+
+    diagnostics
+}
+
+// Phpstan loads a project config automatically and the baseline is usually
+// included from there, so only pass the baseline when there is no config.
+fn baseline_args(project_root: &str) -> Option<Vec<String>> {
+    let baseline = format!("{}/phpstan-baseline.neon", project_root);
+    if metadata(&baseline).is_err() {
+        return None;
+    }
+
+    let has_config = ["phpstan.neon", "phpstan.neon.dist", "phpstan.dist.neon"]
+        .iter()
+        .any(|config| metadata(format!("{}/{}", project_root, config)).is_ok());
+    if has_config {
+        return None;
+    }
+
+    Some(vec![format!("--configuration={}", baseline)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fixture;
+
+    fn uri(path: &str) -> Url {
+        Url::from_file_path(path).unwrap()
+    }
+
+    #[test]
+    fn parses_the_report_per_file() {
+        let report = parse_report(&fixture("phpstan/report.json"), b"").unwrap();
+        let diagnostics = report_diagnostics(
+            &report,
+            &PluginSetting::default(),
+            &uri("/project/src/Foo.php"),
+        );
+
+        let foo = &diagnostics[&uri("/project/src/Foo.php")];
+        assert_eq!(foo.len(), 2);
+        assert_eq!(
+            foo[0].range,
+            Range::new(Position::new(13, 0), Position::new(13, 0))
+        );
+        assert_eq!(foo[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(
+            foo[0].code,
+            Some(NumberOrString::String("argument.type".to_string()))
+        );
+        assert!(foo[1].message.ends_with(
+            "\nTip: Learn more: https://phpstan.org/blog/solving-phpstan-access-to-undefined-property"
+        ));
+
+        let bar = &diagnostics[&uri("/project/src/Bar.php")];
+        assert_eq!(bar.len(), 1);
+        assert_eq!(bar[0].range.start, Position::new(7, 0));
+    }
+
+    #[test]
+    fn file_level_messages_go_on_the_first_line() {
+        let stdout = br#"{"files":{"/project/src/Foo.php":{"messages":[{"message":"Class Foo was not found.","line":null}]}}}"#;
+        let report = parse_report(stdout, b"").unwrap();
+        let diagnostics = report_diagnostics(
+            &report,
+            &PluginSetting::default(),
+            &uri("/project/src/Foo.php"),
+        );
+
+        assert_eq!(
+            diagnostics[&uri("/project/src/Foo.php")][0].range,
+            Range::new(Position::new(0, 0), Position::new(0, 0))
+        );
+    }
+
+    #[test]
+    fn analyse_paths_only_keep_the_saved_file() {
+        let settings = PluginSetting {
+            analyse_paths: vec!["src".to_string()],
+            ..PluginSetting::default()
+        };
+        let report = parse_report(&fixture("phpstan/report.json"), b"").unwrap();
+        let diagnostics = report_diagnostics(&report, &settings, &uri("/project/src/Foo.php"));
+
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics.contains_key(&uri("/project/src/Foo.php")));
+    }
+
+    #[test]
+    fn malformed_output_is_an_error() {
+        let error = parse_report(&fixture("phpstan/fatal.txt"), b"").unwrap_err();
+        assert!(error.starts_with("Fatal error: Allowed memory size of 134217728 bytes exhausted"));
+
+        assert_eq!(
+            parse_report(
+                b"Segmentation fault",
+                b"Note: Using configuration file phpstan.neon."
+            ),
+            Err("Segmentation fault\nNote: Using configuration file phpstan.neon.".to_string())
+        );
+    }
+
+    #[test]
+    fn empty_output_is_an_empty_report() {
+        assert_eq!(
+            parse_report(b"", b"Note: Using configuration file phpstan.neon."),
+            Ok(PhpstanReport::default())
+        );
+        assert_eq!(parse_report(b"", b""), Ok(PhpstanReport::default()));
     }
 }
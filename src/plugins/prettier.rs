@@ -0,0 +1,226 @@
+use std::{format, str, vec};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use log::{error, info};
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, MessageType, Position, Range, Url};
+use tower_lsp::Client;
+
+use crate::plugins::{
+    build_args, execute_plugin, find_executable, find_project_binary, log_execute_error,
+    map_severity, run_with_stdin, stdin_args, Plugin, PluginOutput, PluginSetting,
+};
+
+#[derive(Default)]
+pub struct PrettierPlugin;
+
+#[async_trait]
+impl Plugin for PrettierPlugin {
+    fn get_plugin_id(&self) -> &str {
+        "prettier"
+    }
+
+    fn local_binaries(&self) -> &[&str] {
+        &["node_modules/.bin/prettier"]
+    }
+
+    fn is_installed(
+        &self,
+        settings: DashMap<String, String>,
+        _user_settings: &PluginSetting,
+    ) -> Option<PluginSetting> {
+        let project_root = settings
+            .get("root_uri")
+            .expect("Cant fetch root uri")
+            .to_string()
+            .replace("file://", "");
+
+        let default_args = vec!["--check".to_string()];
+        let default_filetypes = vec![
+            "js".to_string(),
+            "jsx".to_string(),
+            "ts".to_string(),
+            "tsx".to_string(),
+            "css".to_string(),
+            "scss".to_string(),
+            "json".to_string(),
+            "md".to_string(),
+        ];
+
+        let prettier = find_project_binary(&project_root, &["node_modules/.bin/prettier"])
+            .or_else(|| find_executable("prettier"));
+        if let Some(prettier) = prettier {
+            info!("Plugin Prettier found");
+            return Some(PluginSetting {
+                cmd: prettier,
+                args: default_args,
+                filetypes: default_filetypes,
+                ..PluginSetting::default()
+            });
+        }
+
+        error!("Prettier cant be executed.");
+        None
+    }
+
+    async fn run(
+        &self,
+        plugin_settings: PluginSetting,
+        uri: Url,
+        client: Client,
+    ) -> Option<PluginOutput> {
+        let file = uri.to_string().replace("file://", "");
+        let args = build_args(&plugin_settings, &file);
+
+        client
+            .log_message(
+                MessageType::LOG,
+                format!("Running Prettier with command {}", plugin_settings.cmd),
+            )
+            .await;
+
+        let output = match execute_plugin(&plugin_settings, args).await {
+            Ok(output) => output,
+            Err(e) => {
+                log_execute_error(&client, &plugin_settings.cmd, e).await;
+                return None;
+            }
+        };
+
+        // --check exits with 1 for unformatted files and 2 when Prettier fails.
+        let mut plugin_output = PluginOutput::default();
+        let diagnostics = plugin_output.diagnostics.entry(uri.clone()).or_default();
+        match output.status.code() {
+            Some(0) => {}
+            Some(1) => diagnostics.push(Diagnostic::new(
+                Range::new(
+                    Position {
+                        line: 0,
+                        character: 0,
+                    },
+                    Position {
+                        line: 0,
+                        character: 0,
+                    },
+                ),
+                Some(map_severity(
+                    &plugin_settings,
+                    "warning",
+                    DiagnosticSeverity::WARNING,
+                )),
+                None,
+                None,
+                "File is not formatted with Prettier".to_string(),
+                None,
+                None,
+            )),
+            _ => {
+                error!(
+                    "Prettier returned error: {}",
+                    str::from_utf8(&output.stderr).unwrap_or_default()
+                );
+                return None;
+            }
+        }
+
+        client
+            .log_message(MessageType::LOG, "Prettier ended".to_string())
+            .await;
+        Some(plugin_output)
+    }
+
+    fn can_format(&self) -> bool {
+        true
+    }
+
+    async fn format(
+        &self,
+        plugin_settings: PluginSetting,
+        uri: Url,
+        text: String,
+        client: Client,
+    ) -> Option<String> {
+        // The path picks the parser and the config, the source is read from stdin.
+        let file = uri.to_string().replace("file://", "");
+        let mut args: Vec<String> = stdin_args(&plugin_settings, &file)
+            .into_iter()
+            .filter(|arg| arg != "--check")
+            .collect();
+        args.push(format!("--stdin-filepath={}", file));
+
+        client
+            .log_message(
+                MessageType::LOG,
+                format!(
+                    "Running Prettier format with command {}",
+                    plugin_settings.cmd
+                ),
+            )
+            .await;
+
+        let output = match run_with_stdin(
+            &plugin_settings.cmd,
+            args,
+            &plugin_settings.env,
+            &plugin_settings.working_dir,
+            &text,
+            plugin_settings.max_output_bytes,
+        )
+        .await
+        {
+            Ok(output) => output,
+            Err(e) => {
+                error!("Prettier cant be executed: {}", e);
+                return None;
+            }
+        };
+
+        if !output.status.success() {
+            error!(
+                "Prettier returned error: {}",
+                str::from_utf8(&output.stderr).unwrap_or_default()
+            );
+            return None;
+        }
+
+        String::from_utf8(output.stdout).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{temp_dir, test_client};
+
+    #[tokio::test]
+    async fn formats_with_substituted_args() {
+        // Prettier is stood in for by a script printing its arguments.
+        let dir = temp_dir("prettier-format");
+        let settings = PluginSetting {
+            cmd: "sh".to_string(),
+            args: vec![
+                "-c".to_string(),
+                "printf '%s\\n' \"$@\"".to_string(),
+                "prettier".to_string(),
+                "--check".to_string(),
+                "--config={config}".to_string(),
+                "{file}".to_string(),
+            ],
+            config_path: "/project/.prettierrc".to_string(),
+            ..PluginSetting::default()
+        };
+        let uri = Url::from_file_path(dir.join("app.js")).unwrap();
+
+        let formatted = PrettierPlugin
+            .format(settings, uri, "let a=1\n".to_string(), test_client())
+            .await;
+
+        assert_eq!(
+            formatted,
+            Some(format!(
+                "--config=/project/.prettierrc\n--stdin-filepath={}\n",
+                dir.join("app.js").display()
+            ))
+        );
+    }
+}
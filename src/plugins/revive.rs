@@ -0,0 +1,196 @@
+use std::{format, path::Path, str, vec};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use log::{error, info};
+use serde_derive::Deserialize;
+use tower_lsp::lsp_types::{Diagnostic, NumberOrString, Position, Range};
+use tower_lsp::lsp_types::{DiagnosticSeverity, MessageType, Url};
+use tower_lsp::Client;
+
+use crate::plugins::{
+    execute_plugin, find_executable, is_report_file, log_execute_error, map_severity, report_uri,
+    substitute_args, to_character, to_zero_based, Plugin, PluginOutput, PluginSetting,
+};
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Failure {
+    pub severity: String,
+    pub failure: String,
+    pub rule_name: String,
+    pub position: FailurePosition,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct FailurePosition {
+    pub start: Location,
+    pub end: Location,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Location {
+    pub filename: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+#[derive(Default)]
+pub struct RevivePlugin;
+
+#[async_trait]
+impl Plugin for RevivePlugin {
+    fn get_plugin_id(&self) -> &str {
+        "revive"
+    }
+
+    fn is_installed(
+        &self,
+        settings: DashMap<String, String>,
+        _user_settings: &PluginSetting,
+    ) -> Option<PluginSetting> {
+        let project_root = settings
+            .get("root_uri")
+            .expect("Cant fetch root uri")
+            .to_string()
+            .replace("file://", "");
+
+        if !Path::new(&format!("{}/go.mod", project_root)).is_file() {
+            error!("go.mod not found, revive is disabled.");
+            return None;
+        }
+
+        // revive checks all packages of the module, the saved file isn't passed.
+        let default_args = vec![
+            "-formatter".to_string(),
+            "json".to_string(),
+            "{root}/...".to_string(),
+        ];
+        let default_filetypes = vec!["go".to_string()];
+
+        if let Some(revive) = find_executable("revive") {
+            info!("Plugin Revive found");
+            return Some(PluginSetting {
+                cmd: revive,
+                args: default_args,
+                filetypes: default_filetypes,
+                ..PluginSetting::default()
+            });
+        }
+
+        error!("Revive cant be executed.");
+        None
+    }
+
+    fn is_project_scoped(&self) -> bool {
+        true
+    }
+
+    fn checks_whole_project(&self) -> bool {
+        true
+    }
+
+    fn rule_url(&self, rule: &str) -> Option<Url> {
+        Url::parse(&format!(
+            "https://github.com/mgechev/revive/blob/master/RULES_DESCRIPTIONS.md#{}",
+            rule
+        ))
+        .ok()
+    }
+
+    async fn run(
+        &self,
+        plugin_settings: PluginSetting,
+        uri: Url,
+        client: Client,
+    ) -> Option<PluginOutput> {
+        let file = uri.to_string().replace("file://", "");
+        let args = substitute_args(&plugin_settings, &file);
+
+        client
+            .log_message(
+                MessageType::LOG,
+                format!("Running Revive with command {}", plugin_settings.cmd),
+            )
+            .await;
+
+        let output = match execute_plugin(&plugin_settings, args).await {
+            Ok(output) => output,
+            Err(e) => {
+                log_execute_error(&client, &plugin_settings.cmd, e).await;
+                return None;
+            }
+        };
+
+        // No failures are printed as `null`.
+        let failures: Option<Vec<Failure>> = match serde_json::from_slice(&output.stdout) {
+            Ok(failures) => failures,
+            Err(_) => {
+                client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!(
+                            "Revive returned error: {}",
+                            str::from_utf8(&output.stderr).unwrap_or_default(),
+                        ),
+                    )
+                    .await;
+                return None;
+            }
+        };
+
+        // Every file with failures is published under its own uri, the saved
+        // one gets an empty list to clear its previous diagnostics.
+        let mut plugin_output = PluginOutput::default();
+        plugin_output.diagnostics.entry(uri.clone()).or_default();
+        for failure in failures.unwrap_or_default() {
+            let start = &failure.position.start;
+            let end = &failure.position.end;
+
+            let file_uri = if is_report_file(&start.filename, &plugin_settings.root, &uri) {
+                uri.clone()
+            } else {
+                match report_uri(&start.filename, &plugin_settings.root) {
+                    Some(file_uri) => file_uri,
+                    None => continue,
+                }
+            };
+
+            let severity = match &failure.severity[..] {
+                "error" => DiagnosticSeverity::ERROR,
+                _ => DiagnosticSeverity::WARNING,
+            };
+            let severity = map_severity(&plugin_settings, &failure.severity, severity);
+
+            plugin_output
+                .diagnostics
+                .entry(file_uri)
+                .or_default()
+                .push(Diagnostic::new(
+                    Range::new(
+                        Position {
+                            line: to_zero_based(start.line),
+                            character: to_character(start.column, self.column_base()),
+                        },
+                        Position {
+                            line: to_zero_based(end.line),
+                            character: to_character(end.column, self.column_base()),
+                        },
+                    ),
+                    Some(severity),
+                    Some(NumberOrString::String(failure.rule_name.clone())),
+                    None,
+                    failure.failure.clone(),
+                    None,
+                    None,
+                ));
+        }
+
+        client
+            .log_message(MessageType::LOG, "Revive ended".to_string())
+            .await;
+        Some(plugin_output)
+    }
+}
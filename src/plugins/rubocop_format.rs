@@ -0,0 +1,157 @@
+use std::{format, str, vec};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use log::{error, info};
+use tower_lsp::lsp_types::{MessageType, Url};
+use tower_lsp::Client;
+
+use crate::plugins::{
+    find_executable, find_project_binary, run_with_stdin, stdin_args, Plugin, PluginOutput,
+    PluginSetting,
+};
+
+#[derive(Default)]
+pub struct RubocopFormatPlugin;
+
+#[async_trait]
+impl Plugin for RubocopFormatPlugin {
+    fn get_plugin_id(&self) -> &str {
+        "rubocop-format"
+    }
+
+    fn local_binaries(&self) -> &[&str] {
+        &["bin/rubocop"]
+    }
+
+    fn is_installed(
+        &self,
+        settings: DashMap<String, String>,
+        _user_settings: &PluginSetting,
+    ) -> Option<PluginSetting> {
+        let project_root = settings
+            .get("root_uri")
+            .expect("Cant fetch root uri")
+            .to_string()
+            .replace("file://", "");
+
+        let default_filetypes = vec!["rb".to_string()];
+
+        // Bundler installs a binstub, which runs the project rubocop version.
+        let rubocop = find_project_binary(&project_root, &["bin/rubocop"])
+            .or_else(|| find_executable("rubocop"));
+        if let Some(rubocop) = rubocop {
+            info!("Plugin Rubocop format found");
+            return Some(PluginSetting {
+                cmd: rubocop,
+                args: vec![],
+                filetypes: default_filetypes,
+                ..PluginSetting::default()
+            });
+        }
+
+        error!("Rubocop cant be executed.");
+        None
+    }
+
+    async fn run(
+        &self,
+        _plugin_settings: PluginSetting,
+        _uri: Url,
+        _client: Client,
+    ) -> Option<PluginOutput> {
+        // Formatting only.
+        None
+    }
+
+    fn can_format(&self) -> bool {
+        true
+    }
+
+    async fn format(
+        &self,
+        plugin_settings: PluginSetting,
+        uri: Url,
+        text: String,
+        client: Client,
+    ) -> Option<String> {
+        // With --stderr the report goes to stderr and stdout only holds the
+        // corrected source.
+        let file = uri.to_string().replace("file://", "");
+        let mut args = stdin_args(&plugin_settings, &file);
+        args.push("-A".to_string());
+        args.push("--stderr".to_string());
+        args.push("--stdin".to_string());
+        args.push(file);
+
+        client
+            .log_message(
+                MessageType::LOG,
+                format!("Running Rubocop -A with command {}", plugin_settings.cmd),
+            )
+            .await;
+
+        let output = match run_with_stdin(
+            &plugin_settings.cmd,
+            args,
+            &plugin_settings.env,
+            &plugin_settings.working_dir,
+            &text,
+            plugin_settings.max_output_bytes,
+        )
+        .await
+        {
+            Ok(output) => output,
+            Err(e) => {
+                error!("Rubocop cant be executed: {}", e);
+                return None;
+            }
+        };
+
+        // Rubocop exits with 1 when offenses remain, the source is still corrected.
+        if output.stdout.is_empty() {
+            error!(
+                "Rubocop returned error: {}",
+                str::from_utf8(&output.stderr).unwrap_or_default()
+            );
+            return None;
+        }
+
+        String::from_utf8(output.stdout).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, temp_dir, test_client, write_file};
+
+    #[tokio::test]
+    async fn returns_the_corrected_source() {
+        // Rubocop is stood in for by a script printing a recorded run, the
+        // source on stdout and the offenses it couldn't correct on stderr.
+        let dir = temp_dir("rubocop-format");
+        for name in ["corrected.rb", "offenses.txt"] {
+            let recorded = fixture(&format!("rubocop/{}", name));
+            write_file(&dir, name, str::from_utf8(&recorded).unwrap());
+        }
+        let script = "cat > /dev/null; cat corrected.rb; cat offenses.txt >&2; exit 1";
+        let settings = PluginSetting {
+            cmd: "sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string(), "rubocop".to_string()],
+            working_dir: dir.to_string_lossy().to_string(),
+            ..PluginSetting::default()
+        };
+        let uri = Url::from_file_path(dir.join("app/greet.rb")).unwrap();
+        let unformatted = "def greet name\n    puts 'Hello, #{name}'\n    unused = 1\nend\n";
+
+        let formatted = RubocopFormatPlugin
+            .format(settings, uri, unformatted.to_string(), test_client())
+            .await;
+
+        assert_eq!(
+            formatted,
+            Some(String::from_utf8(fixture("rubocop/corrected.rb")).unwrap())
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use std::{format, str, vec};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use log::{error, info};
+use tower_lsp::lsp_types::{MessageType, Url};
+use tower_lsp::Client;
+
+use crate::plugins::{
+    find_executable, find_project_binary, run_with_stdin, stdin_args, Plugin, PluginOutput,
+    PluginSetting,
+};
+
+#[derive(Default)]
+pub struct SqlfluffFormatPlugin;
+
+#[async_trait]
+impl Plugin for SqlfluffFormatPlugin {
+    fn get_plugin_id(&self) -> &str {
+        "sqlfluff-format"
+    }
+
+    fn local_binaries(&self) -> &[&str] {
+        &[".venv/bin/sqlfluff"]
+    }
+
+    fn is_installed(
+        &self,
+        settings: DashMap<String, String>,
+        _user_settings: &PluginSetting,
+    ) -> Option<PluginSetting> {
+        let project_root = settings
+            .get("root_uri")
+            .expect("Cant fetch root uri")
+            .to_string()
+            .replace("file://", "");
+
+        let default_filetypes = vec!["sql".to_string()];
+
+        let sqlfluff = find_project_binary(&project_root, &[".venv/bin/sqlfluff"])
+            .or_else(|| find_executable("sqlfluff"));
+        if let Some(sqlfluff) = sqlfluff {
+            info!("Plugin Sqlfluff format found");
+            return Some(PluginSetting {
+                cmd: sqlfluff,
+                args: vec![],
+                filetypes: default_filetypes,
+                ..PluginSetting::default()
+            });
+        }
+
+        error!("Sqlfluff cant be executed.");
+        None
+    }
+
+    async fn run(
+        &self,
+        _plugin_settings: PluginSetting,
+        _uri: Url,
+        _client: Client,
+    ) -> Option<PluginOutput> {
+        // Formatting only.
+        None
+    }
+
+    fn can_format(&self) -> bool {
+        true
+    }
+
+    async fn format(
+        &self,
+        plugin_settings: PluginSetting,
+        uri: Url,
+        text: String,
+        client: Client,
+    ) -> Option<String> {
+        // The file name is only used to resolve the sqlfluff config.
+        let file = uri.to_string().replace("file://", "");
+        let mut args = vec!["fix".to_string()];
+        args.extend(stdin_args(&plugin_settings, &file));
+        args.push(format!("--stdin-filename={}", file));
+        args.push("-".to_string());
+
+        client
+            .log_message(
+                MessageType::LOG,
+                format!("Running Sqlfluff fix with command {}", plugin_settings.cmd),
+            )
+            .await;
+
+        let output = match run_with_stdin(
+            &plugin_settings.cmd,
+            args,
+            &plugin_settings.env,
+            &plugin_settings.working_dir,
+            &text,
+            plugin_settings.max_output_bytes,
+        )
+        .await
+        {
+            Ok(output) => output,
+            Err(e) => {
+                error!("Sqlfluff cant be executed: {}", e);
+                return None;
+            }
+        };
+
+        // Sqlfluff exits with 1 when unfixable violations remain, the fixable
+        // ones are still applied to the returned SQL.
+        if output.stdout.is_empty() {
+            error!(
+                "Sqlfluff returned error: {}",
+                str::from_utf8(&output.stderr).unwrap_or_default()
+            );
+            return None;
+        }
+
+        String::from_utf8(output.stdout).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{temp_dir, test_client, write_file};
+
+    #[tokio::test]
+    async fn formats_messy_sql() {
+        // Sqlfluff is stood in for by a script, run by sh as the "fix" arg.
+        let dir = temp_dir("sqlfluff-format");
+        write_file(
+            &dir,
+            "fix",
+            "cat > /dev/null\nprintf 'SELECT a\\nFROM t\\n'\nexit 1\n",
+        );
+        let settings = PluginSetting {
+            cmd: "sh".to_string(),
+            working_dir: dir.to_string_lossy().to_string(),
+            ..PluginSetting::default()
+        };
+        let uri = Url::from_file_path(dir.join("query.sql")).unwrap();
+
+        let formatted = SqlfluffFormatPlugin
+            .format(settings, uri, "select a from t".to_string(), test_client())
+            .await;
+
+        assert_eq!(formatted, Some("SELECT a\nFROM t\n".to_string()));
+    }
+}
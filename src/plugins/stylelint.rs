@@ -1,18 +1,29 @@
-use std::{format, fs::metadata, process::Command, str, vec};
+use std::{format, str, vec};
 
 use async_trait::async_trait;
 use dashmap::DashMap;
 use log::{error, info};
 use serde_json::Value;
-use tower_lsp::lsp_types::{Diagnostic, Position, Range};
-use tower_lsp::lsp_types::{DiagnosticSeverity, MessageType, Url};
-use tower_lsp::Client;
+use tower_lsp::lsp_types::{Diagnostic, NumberOrString, Position, Range};
+use tower_lsp::lsp_types::{DiagnosticSeverity, DiagnosticTag, Url};
 
-use crate::plugins::{Plugin, PluginOutput, PluginSetting};
+use crate::plugins::{
+    config_args, find_executable, find_project_binary, map_severity, stdin_args, to_character,
+    to_zero_based, Plugin, PluginSetting,
+};
 use serde_derive::Deserialize;
 
 pub type StylelintReport = Vec<FileReport>;
 
+// Depending on the version and formatter flags the results are a bare array,
+// or wrapped in an object.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum StylelintOutput {
+    Results(StylelintReport),
+    Wrapped { results: StylelintReport },
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FileReport {
@@ -45,23 +56,50 @@ impl Plugin for StylelintPlugin {
         "stylelint"
     }
 
-    fn is_installed(&self, settings: DashMap<String, String>) -> Option<PluginSetting> {
+    fn local_binaries(&self) -> &[&str] {
+        &[
+            "node_modules/.bin/stylelint_d",
+            "node_modules/.bin/stylelint",
+        ]
+    }
+
+    fn is_installed(
+        &self,
+        settings: DashMap<String, String>,
+        user_settings: &PluginSetting,
+    ) -> Option<PluginSetting> {
         let project_root = settings
             .get("root_uri")
             .expect("Cant fetch root uri")
             .to_string()
             .replace("file://", "");
 
-        let project_stylelint = format!("{}/node_modules/.bin/stylelint", project_root);
-        let default_args = vec!["-f=json".to_string()];
+        let mut default_args = vec!["-f=json".to_string()];
+        default_args.extend(config_args(user_settings, "--config="));
         let default_filetypes = vec!["css".to_string(), "less".to_string(), "sass".to_string()];
 
-        if metadata(project_stylelint.clone()).is_ok() {
+        // Prefer the stylelint_d daemon, it avoids the startup cost on every run.
+        let daemon = find_project_binary(&project_root, &["node_modules/.bin/stylelint_d"])
+            .or_else(|| find_executable("stylelint_d"));
+        if let Some(daemon) = daemon {
+            info!("Plugin Stylelint found, using the stylelint_d daemon");
+            return Some(PluginSetting {
+                cmd: daemon,
+                args: default_args,
+                filetypes: default_filetypes,
+                ..PluginSetting::default()
+            });
+        }
+
+        if let Some(project_stylelint) =
+            find_project_binary(&project_root, &["node_modules/.bin/stylelint"])
+        {
             info!("Plugin Stylelint found");
             return Some(PluginSetting {
                 cmd: project_stylelint,
                 args: default_args,
                 filetypes: default_filetypes,
+                ..PluginSetting::default()
             });
         }
 
@@ -69,84 +107,192 @@ impl Plugin for StylelintPlugin {
         None
     }
 
-    async fn run(
-        &self,
-        plugin_settings: PluginSetting,
-        uri: Url,
-        client: Client,
-    ) -> Option<PluginOutput> {
-        // Append file to args.
+    fn rule_url(&self, rule: &str) -> Option<Url> {
+        // Rules from stylelint plugins are namespaced, e.g. "scss/at-rule-no-unknown".
+        if rule.contains('/') {
+            return None;
+        }
+
+        Url::parse(&format!("https://stylelint.io/user-guide/rules/{}", rule)).ok()
+    }
+
+    fn rule_tags(&self, rule: &str) -> Option<Vec<DiagnosticTag>> {
+        // E.g. "declaration-property-value-keyword-no-deprecated".
+        if rule.ends_with("-no-deprecated") {
+            return Some(vec![DiagnosticTag::DEPRECATED]);
+        }
+
+        None
+    }
+
+    fn stdin_command(&self, plugin_settings: &PluginSetting, uri: &Url) -> Option<Vec<String>> {
         let file = uri.to_string().replace("file://", "");
-        let mut args = plugin_settings.args.clone();
-        args.push(file);
-
-        client
-            .log_message(
-                MessageType::LOG,
-                format!("Running PHPCS with command {}", plugin_settings.cmd),
-            )
-            .await;
-
-        let output = Command::new(plugin_settings.cmd)
-            .args(args)
-            .output()
-            .expect("failed to execute process");
-
-        if !output.stderr.is_empty() {
-            error!(
-                "Stylelint returned error: {}",
-                str::from_utf8(&output.stderr).unwrap()
-            );
+        let mut args = stdin_args(plugin_settings, &file);
+        args.push(format!("--stdin-filename={}", file));
+        Some(args)
+    }
 
-            return None;
+    fn parse(
+        &self,
+        plugin_settings: &PluginSetting,
+        _uri: &Url,
+        stdout: &[u8],
+        stderr: &[u8],
+    ) -> Result<Vec<Diagnostic>, String> {
+        if !stderr.is_empty() {
+            return Err(String::from_utf8_lossy(stderr).to_string());
         }
 
-        let report: StylelintReport = serde_json::from_slice(&output.stdout).unwrap_or_default();
-
-        for file_report in report {
-            let mut diagnostics = vec![];
-            for message in &file_report.warnings {
-                let mut severity = DiagnosticSeverity::INFORMATION;
-
-                match &message.severity[..] {
-                    "warning" => severity = DiagnosticSeverity::WARNING,
-                    "error" => severity = DiagnosticSeverity::ERROR,
-                    _ => {}
-                }
-
-                let line_as_u32: u32 = message.line.try_into().unwrap();
-                let end_line_as_u32: u32 = message.end_line.try_into().unwrap();
-
-                let item = Diagnostic::new(
-                    Range::new(
-                        Position {
-                            line: line_as_u32 - 1,
-                            character: message.column.try_into().unwrap(),
-                        },
-                        Position {
-                            line: end_line_as_u32 - 1,
-                            character: message.column.try_into().unwrap(),
-                        },
-                    ),
-                    Some(severity),
-                    None,
-                    None,
-                    message.text.clone(),
-                    None,
-                    None,
-                );
-
-                diagnostics.push(item);
+        Ok(report_diagnostics(
+            &parse_report(stdout)?,
+            plugin_settings,
+            self.column_base(),
+        ))
+    }
+}
+
+fn parse_report(stdout: &[u8]) -> Result<StylelintReport, String> {
+    match serde_json::from_slice(stdout) {
+        Ok(StylelintOutput::Results(report)) => Ok(report),
+        Ok(StylelintOutput::Wrapped { results }) => Ok(results),
+        // No output at all means there is nothing to report.
+        Err(_) if stdout.iter().all(u8::is_ascii_whitespace) => Ok(StylelintReport::default()),
+        Err(e) => Err(format!("can't parse the report: {}", e)),
+    }
+}
+
+// Turn the report into diagnostics, without running anything.
+fn report_diagnostics(
+    report: &StylelintReport,
+    plugin_settings: &PluginSetting,
+    column_base: u32,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    for file_report in report {
+        for message in &file_report.warnings {
+            let mut severity = DiagnosticSeverity::INFORMATION;
+
+            match &message.severity[..] {
+                "warning" => severity = DiagnosticSeverity::WARNING,
+                "error" => severity = DiagnosticSeverity::ERROR,
+                _ => {}
             }
+            let severity = map_severity(plugin_settings, &message.severity, severity);
+
+            let line_as_u32: u32 = message.line.try_into().unwrap();
+            let end_line_as_u32: u32 = message.end_line.try_into().unwrap();
+            let column_as_u32: u32 = message.column.try_into().unwrap();
+            let end_column_as_u32: u32 = message.end_column.try_into().unwrap_or(column_as_u32);
+
+            let item = Diagnostic::new(
+                Range::new(
+                    Position {
+                        line: to_zero_based(line_as_u32),
+                        character: to_character(column_as_u32, column_base),
+                    },
+                    Position {
+                        line: to_zero_based(end_line_as_u32),
+                        character: to_character(end_column_as_u32, column_base),
+                    },
+                ),
+                Some(severity),
+                Some(NumberOrString::String(message.rule.clone())),
+                None,
+                message.text.clone(),
+                None,
+                None,
+            );
 
-            client
-                .publish_diagnostics(uri.clone(), diagnostics, Some(1))
-                .await;
+            diagnostics.push(item);
         }
+    }
 
-        client
-            .log_message(MessageType::LOG, "Stylelint ended".to_string())
-            .await;
-        None
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, temp_dir, write_file};
+
+    fn parse(stdout: &[u8], stderr: &[u8]) -> Result<Vec<Diagnostic>, String> {
+        StylelintPlugin.parse(
+            &PluginSetting::default(),
+            &Url::from_file_path("/project/src/app.css").unwrap(),
+            stdout,
+            stderr,
+        )
+    }
+
+    #[test]
+    fn parses_the_report() {
+        let diagnostics = parse(&fixture("stylelint/report.json"), b"").unwrap();
+
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(2, 9), Position::new(2, 13))
+        );
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(
+            diagnostics[0].code,
+            Some(NumberOrString::String("color-no-invalid-hex".to_string()))
+        );
+        assert_eq!(
+            diagnostics[1].range,
+            Range::new(Position::new(6, 2), Position::new(6, 14))
+        );
+        assert_eq!(diagnostics[1].severity, Some(DiagnosticSeverity::WARNING));
+    }
+
+    #[test]
+    fn parses_the_wrapped_report() {
+        let bare = parse(&fixture("stylelint/report.json"), b"").unwrap();
+        let wrapped = parse(&fixture("stylelint/wrapped.json"), b"").unwrap();
+
+        assert_eq!(wrapped.len(), 2);
+        assert_eq!(wrapped, bare);
+    }
+
+    #[test]
+    fn malformed_output_is_an_error() {
+        assert!(parse(b"TypeError: Cannot read properties of undefined", b"").is_err());
+        assert_eq!(
+            parse(b"", b"Error: No configuration provided"),
+            Err("Error: No configuration provided".to_string())
+        );
+        assert_eq!(parse(b"\n", b""), Ok(vec![]));
+    }
+
+    #[test]
+    fn prefers_the_daemon() {
+        let root = temp_dir("stylelint-daemon");
+        write_file(&root, "node_modules/.bin/stylelint", "");
+        let daemon = write_file(&root, "node_modules/.bin/stylelint_d", "");
+        let settings = DashMap::new();
+        settings.insert("root_uri".to_string(), format!("file://{}", root.display()));
+
+        let plugin_settings = StylelintPlugin
+            .is_installed(settings, &PluginSetting::default())
+            .unwrap();
+        assert_eq!(plugin_settings.cmd, daemon.to_string_lossy());
+    }
+
+    #[test]
+    fn lints_stdin_with_the_real_path() {
+        let settings = PluginSetting {
+            args: vec!["-f".to_string(), "json".to_string()],
+            ..PluginSetting::default()
+        };
+        let uri = Url::from_file_path("/project/src/app.css").unwrap();
+
+        assert_eq!(
+            StylelintPlugin.stdin_command(&settings, &uri),
+            Some(vec![
+                "-f".to_string(),
+                "json".to_string(),
+                "--stdin-filename=/project/src/app.css".to_string(),
+            ])
+        );
     }
 }
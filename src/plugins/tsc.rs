@@ -0,0 +1,263 @@
+use std::{
+    collections::HashMap,
+    format,
+    path::{Path, PathBuf},
+    str, vec,
+};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use log::{error, info};
+use regex::Regex;
+use tower_lsp::lsp_types::{
+    Diagnostic, DiagnosticRelatedInformation, Location, NumberOrString, Position, Range,
+};
+use tower_lsp::lsp_types::{DiagnosticSeverity, MessageType, Url};
+use tower_lsp::Client;
+
+use crate::plugins::{
+    execute_plugin, find_project_binary, log_execute_error, map_severity, substitute_args,
+    to_character, to_zero_based, Plugin, PluginOutput, PluginSetting,
+};
+
+#[derive(Default)]
+pub struct TscPlugin;
+
+#[async_trait]
+impl Plugin for TscPlugin {
+    fn get_plugin_id(&self) -> &str {
+        "tsc"
+    }
+
+    fn local_binaries(&self) -> &[&str] {
+        &["node_modules/.bin/tsc"]
+    }
+
+    fn is_installed(
+        &self,
+        settings: DashMap<String, String>,
+        _user_settings: &PluginSetting,
+    ) -> Option<PluginSetting> {
+        let project_root = settings
+            .get("root_uri")
+            .expect("Cant fetch root uri")
+            .to_string()
+            .replace("file://", "");
+
+        if !Path::new(&format!("{}/tsconfig.json", project_root)).is_file() {
+            error!("tsconfig.json not found, tsc is disabled.");
+            return None;
+        }
+
+        // tsc checks the whole project, the saved file isn't passed.
+        let default_args = vec![
+            "--noEmit".to_string(),
+            "--pretty".to_string(),
+            "false".to_string(),
+            "-p".to_string(),
+            "{root}/tsconfig.json".to_string(),
+        ];
+        let default_filetypes = vec!["ts".to_string(), "tsx".to_string()];
+
+        if let Some(tsc) = find_project_binary(&project_root, &["node_modules/.bin/tsc"]) {
+            info!("Plugin Tsc found");
+            return Some(PluginSetting {
+                cmd: tsc,
+                args: default_args,
+                filetypes: default_filetypes,
+                ..PluginSetting::default()
+            });
+        }
+
+        error!("Tsc cant be executed.");
+        None
+    }
+
+    fn is_project_scoped(&self) -> bool {
+        true
+    }
+
+    fn checks_whole_project(&self) -> bool {
+        true
+    }
+
+    async fn run(
+        &self,
+        plugin_settings: PluginSetting,
+        uri: Url,
+        client: Client,
+    ) -> Option<PluginOutput> {
+        let file = uri.to_string().replace("file://", "");
+        let args = substitute_args(&plugin_settings, &file);
+
+        client
+            .log_message(
+                MessageType::LOG,
+                format!("Running Tsc with command {}", plugin_settings.cmd),
+            )
+            .await;
+
+        let output = match execute_plugin(&plugin_settings, args).await {
+            Ok(output) => output,
+            Err(e) => {
+                log_execute_error(&client, &plugin_settings.cmd, e).await;
+                return None;
+            }
+        };
+
+        // tsc exits with 2 when it finds errors and prints them to stdout.
+        if output.stdout.is_empty() && !output.stderr.is_empty() {
+            error!(
+                "Tsc returned error: {}",
+                str::from_utf8(&output.stderr).unwrap_or_default()
+            );
+            return None;
+        }
+
+        // Every file with problems is published under its own uri.
+        let plugin_output = PluginOutput {
+            diagnostics: parse_output(
+                str::from_utf8(&output.stdout).unwrap_or_default(),
+                &plugin_settings,
+                self.column_base(),
+            ),
+        };
+
+        client
+            .log_message(MessageType::LOG, "Tsc ended".to_string())
+            .await;
+        Some(plugin_output)
+    }
+}
+
+// Parse `file(line,col): error TSxxxx: message` lines. Paths are relative to
+// the working directory. Indented lines continue the previous message, or
+// point to a related location, e.g. where the expected type is declared.
+fn parse_output(
+    output: &str,
+    plugin_settings: &PluginSetting,
+    column_base: u32,
+) -> HashMap<Url, Vec<Diagnostic>> {
+    let line_regex =
+        Regex::new(r"^(.+?)\((\d+),(\d+)\): (error|warning|message) (TS\d+): (.*)$").unwrap();
+    let related_regex = Regex::new(r"^\s+(.+?)\((\d+),(\d+)\): (.*)$").unwrap();
+    let working_dir = match plugin_settings.working_dir.is_empty() {
+        true => std::env::current_dir().unwrap_or_default(),
+        false => PathBuf::from(&plugin_settings.working_dir),
+    };
+
+    let mut diagnostics: HashMap<Url, Vec<Diagnostic>> = HashMap::new();
+    let mut last_uri: Option<Url> = None;
+
+    for line in output.lines() {
+        let captures = match line_regex.captures(line) {
+            Some(captures) => captures,
+            None if line.starts_with(' ') => {
+                let last_diagnostic = last_uri
+                    .as_ref()
+                    .and_then(|last_uri| diagnostics.get_mut(last_uri))
+                    .and_then(|file_diagnostics| file_diagnostics.last_mut());
+                let last_diagnostic = match last_diagnostic {
+                    Some(last_diagnostic) => last_diagnostic,
+                    None => continue,
+                };
+
+                let related_information = related_regex.captures(line).and_then(|related| {
+                    let uri = Url::from_file_path(working_dir.join(&related[1])).ok()?;
+                    Some(DiagnosticRelatedInformation {
+                        location: Location {
+                            uri,
+                            range: position_range(&related[2], &related[3], column_base),
+                        },
+                        message: related[4].to_string(),
+                    })
+                });
+                match related_information {
+                    Some(related_information) => last_diagnostic
+                        .related_information
+                        .get_or_insert_with(Vec::new)
+                        .push(related_information),
+                    None => {
+                        last_diagnostic.message.push('\n');
+                        last_diagnostic.message.push_str(line.trim());
+                    }
+                }
+                continue;
+            }
+            None => continue,
+        };
+
+        let uri = match Url::from_file_path(working_dir.join(&captures[1])) {
+            Ok(uri) => uri,
+            Err(_) => continue,
+        };
+
+        let severity = match &captures[4] {
+            "error" => DiagnosticSeverity::ERROR,
+            "warning" => DiagnosticSeverity::WARNING,
+            // Suggestions, e.g. about unused code.
+            _ => DiagnosticSeverity::HINT,
+        };
+        let severity = map_severity(plugin_settings, &captures[4], severity);
+
+        let item = Diagnostic::new(
+            position_range(&captures[2], &captures[3], column_base),
+            Some(severity),
+            Some(NumberOrString::String(captures[5].to_string())),
+            None,
+            captures[6].to_string(),
+            None,
+            None,
+        );
+
+        diagnostics.entry(uri.clone()).or_default().push(item);
+        last_uri = Some(uri);
+    }
+
+    diagnostics
+}
+
+// An empty range at a 1-based line and a column counted from column_base.
+fn position_range(line: &str, column: &str, column_base: u32) -> Range {
+    let position = Position {
+        line: to_zero_based(line.parse().unwrap_or(1)),
+        character: to_character(column.parse().unwrap_or(1), column_base),
+    };
+    Range::new(position, position)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fixture;
+
+    #[test]
+    fn attaches_related_locations_in_other_files() {
+        let settings = PluginSetting {
+            working_dir: "/project".to_string(),
+            ..PluginSetting::default()
+        };
+        let output = String::from_utf8(fixture("tsc/output.txt")).unwrap();
+
+        let diagnostics = parse_output(&output, &settings, 1);
+
+        let app = &diagnostics[&Url::from_file_path("/project/src/app.ts").unwrap()];
+        assert_eq!(app.len(), 2);
+        assert_eq!(app[0].range.start, Position::new(4, 2));
+        assert_eq!(
+            app[0].related_information,
+            Some(vec![DiagnosticRelatedInformation {
+                location: Location {
+                    uri: Url::from_file_path("/project/src/types.ts").unwrap(),
+                    range: Range::new(Position::new(1, 2), Position::new(1, 2)),
+                },
+                message: "The expected type comes from property 'id' which is declared here on type 'User'".to_string(),
+            }])
+        );
+        assert_eq!(
+            app[1].message,
+            "Argument of type 'string' is not assignable to parameter of type 'number'.\nType 'string' is not assignable to type 'number'."
+        );
+        assert_eq!(app[1].related_information, None);
+    }
+}
@@ -0,0 +1,143 @@
+use std::{collections::HashMap, format, path::Path, str, vec};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use log::{error, info};
+use tower_lsp::lsp_types::{CodeDescription, Diagnostic, NumberOrString, Position, Range};
+use tower_lsp::lsp_types::{DiagnosticSeverity, Url};
+
+use crate::plugins::{
+    find_executable, map_severity, to_character, to_zero_based, Plugin, PluginSetting,
+};
+use serde_derive::Deserialize;
+
+type ValeReport = HashMap<String, Vec<Alert>>;
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Alert {
+    pub line: u32,
+    // Start and end column, both inclusive.
+    pub span: (u32, u32),
+    pub check: String,
+    pub severity: String,
+    pub message: String,
+    #[serde(default)]
+    pub link: String,
+}
+
+#[derive(Default)]
+pub struct ValePlugin;
+
+#[async_trait]
+impl Plugin for ValePlugin {
+    fn get_plugin_id(&self) -> &str {
+        "vale"
+    }
+
+    fn is_installed(
+        &self,
+        settings: DashMap<String, String>,
+        _user_settings: &PluginSetting,
+    ) -> Option<PluginSetting> {
+        let project_root = settings
+            .get("root_uri")
+            .expect("Cant fetch root uri")
+            .to_string()
+            .replace("file://", "");
+
+        // Vale does nothing useful without a project config.
+        if !Path::new(&format!("{}/.vale.ini", project_root)).is_file() {
+            error!(".vale.ini not found, Vale is disabled.");
+            return None;
+        }
+
+        let default_args = vec!["--output=JSON".to_string()];
+        let default_filetypes = vec!["md".to_string(), "txt".to_string(), "rst".to_string()];
+
+        if let Some(vale) = find_executable("vale") {
+            info!("Plugin Vale found");
+            return Some(PluginSetting {
+                cmd: vale,
+                args: default_args,
+                filetypes: default_filetypes,
+                ..PluginSetting::default()
+            });
+        }
+
+        error!("Vale cant be executed.");
+        None
+    }
+
+    fn parse(
+        &self,
+        plugin_settings: &PluginSetting,
+        _uri: &Url,
+        stdout: &[u8],
+        stderr: &[u8],
+    ) -> Result<Vec<Diagnostic>, String> {
+        // Vale exits with 1 when it finds errors, so only the report matters.
+        let report: ValeReport = serde_json::from_slice(stdout)
+            .map_err(|_| String::from_utf8_lossy(stderr).to_string())?;
+
+        let mut diagnostics = vec![];
+        for alert in report.into_values().flatten() {
+            let severity = match &alert.severity[..] {
+                "error" => DiagnosticSeverity::ERROR,
+                "warning" => DiagnosticSeverity::WARNING,
+                "suggestion" => DiagnosticSeverity::HINT,
+                _ => DiagnosticSeverity::INFORMATION,
+            };
+            let severity = map_severity(plugin_settings, &alert.severity, severity);
+
+            let line = to_zero_based(alert.line);
+            let mut item = Diagnostic::new(
+                Range::new(
+                    Position {
+                        line,
+                        character: to_character(alert.span.0, self.column_base()),
+                    },
+                    Position {
+                        line,
+                        character: to_character(alert.span.1.saturating_add(1), self.column_base()),
+                    },
+                ),
+                Some(severity),
+                Some(NumberOrString::String(alert.check.clone())),
+                None,
+                alert.message.clone(),
+                None,
+                None,
+            );
+
+            // Styles link their own rule docs.
+            item.code_description = Url::parse(&alert.link)
+                .ok()
+                .map(|href| CodeDescription { href });
+
+            diagnostics.push(item);
+        }
+
+        Ok(diagnostics)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_the_inclusive_span() {
+        let report = br#"{"/project/README.md":[{"Line":3,"Span":[5,9],"Check":"Vale.Spelling","Severity":"error","Message":"Did you really mean 'teh'?"}]}"#;
+        let uri = Url::from_file_path("/project/README.md").unwrap();
+
+        let diagnostics = ValePlugin
+            .parse(&PluginSetting::default(), &uri, report, b"")
+            .unwrap();
+
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(2, 4), Position::new(2, 9))
+        );
+    }
+}
@@ -0,0 +1,39 @@
+// Helpers shared by the unit tests.
+
+use std::path::PathBuf;
+
+use futures::StreamExt;
+use tower_lsp::{Client, LspService};
+
+use crate::lsp::Lsp;
+
+// A recorded tool output from tests/fixtures.
+pub fn fixture(name: &str) -> Vec<u8> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name);
+    std::fs::read(&path).unwrap_or_else(|e| panic!("Cant read fixture {}: {}", path.display(), e))
+}
+
+// An empty scratch directory for the test, removed and recreated on each run.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("checkmate-test-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// Create a file, and its parent directories, in a scratch directory.
+pub fn write_file(dir: &std::path::Path, name: &str, contents: &str) -> PathBuf {
+    let path = dir.join(name);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+// A client which isn't connected to an editor, its messages are thrown away.
+pub fn test_client() -> Client {
+    let (service, socket) = LspService::new(Lsp::new);
+    tokio::spawn(socket.for_each(|_| async {}));
+    service.inner().client.clone()
+}
@@ -0,0 +1,104 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use tower_lsp::lsp_types::{Diagnostic, Url};
+use tower_lsp::Client;
+
+// Keeps a minimum interval between diagnostic publishes to the client.
+// Publishes within the interval are queued, and only the latest
+// diagnostics of each file are sent once the interval passes.
+#[derive(Default)]
+pub struct PublishThrottle {
+    interval_ms: AtomicU64,
+    last_publish: Arc<Mutex<Option<Instant>>>,
+    pending: Arc<DashMap<Url, Vec<Diagnostic>>>,
+    flush_scheduled: Arc<AtomicBool>,
+}
+
+impl PublishThrottle {
+    pub fn set_interval(&self, interval: Duration) {
+        self.interval_ms
+            .store(interval.as_millis() as u64, Ordering::SeqCst);
+    }
+
+    pub async fn publish(&self, client: &Client, uri: Url, diagnostics: Vec<Diagnostic>) {
+        let interval = Duration::from_millis(self.interval_ms.load(Ordering::SeqCst));
+        if interval.is_zero() {
+            client.publish_diagnostics(uri, diagnostics, None).await;
+            return;
+        }
+
+        // Replaces any queued diagnostics for the file.
+        self.pending.insert(uri, diagnostics);
+
+        if self.flush_scheduled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let wait = self
+            .last_publish
+            .lock()
+            .unwrap()
+            .map(|last_publish| interval.saturating_sub(last_publish.elapsed()))
+            .unwrap_or_default();
+
+        let client = client.clone();
+        let last_publish = self.last_publish.clone();
+        let pending = self.pending.clone();
+        let flush_scheduled = self.flush_scheduled.clone();
+
+        tokio::spawn(async move {
+            tokio::time::sleep(wait).await;
+
+            // Publishes queued from here on schedule the next flush.
+            flush_scheduled.store(false, Ordering::SeqCst);
+
+            let uris: Vec<Url> = pending.iter().map(|entry| entry.key().clone()).collect();
+            for uri in uris {
+                if let Some((uri, diagnostics)) = pending.remove(&uri) {
+                    client.publish_diagnostics(uri, diagnostics, None).await;
+                }
+            }
+
+            *last_publish.lock().unwrap() = Some(Instant::now());
+        });
+    }
+
+    // Whether diagnostics of the file wait for the interval to pass.
+    #[cfg(test)]
+    pub fn is_pending(&self, uri: &Url) -> bool {
+        self.pending.contains_key(uri)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_client;
+    use tower_lsp::lsp_types::Range;
+
+    #[tokio::test]
+    async fn coalesces_queued_publishes_per_file() {
+        let client = test_client();
+        let throttle = PublishThrottle::default();
+        throttle.set_interval(Duration::from_millis(200));
+        *throttle.last_publish.lock().unwrap() = Some(Instant::now());
+        let uri = Url::parse("file:///project/a.php").unwrap();
+        let first = Diagnostic::new_simple(Range::default(), "first".to_string());
+        let latest = Diagnostic::new_simple(Range::default(), "latest".to_string());
+
+        throttle.publish(&client, uri.clone(), vec![first]).await;
+        throttle
+            .publish(&client, uri.clone(), vec![latest.clone()])
+            .await;
+
+        assert_eq!(throttle.pending.len(), 1);
+        assert_eq!(*throttle.pending.get(&uri).unwrap(), vec![latest]);
+
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        assert!(throttle.pending.is_empty());
+        assert!(!throttle.flush_scheduled.load(Ordering::SeqCst));
+    }
+}
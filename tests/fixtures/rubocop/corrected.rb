@@ -0,0 +1,6 @@
+# frozen_string_literal: true
+
+def greet(name)
+  puts "Hello, #{name}"
+  unused = 1
+end